@@ -0,0 +1,69 @@
+use bevy::{input::mouse::MouseMotion, prelude::*};
+
+const STICK_DEADZONE: f32 = 0.2;
+/// how fast the aim cursor moves at full tilt, in pixels per second
+const AIM_SPEED: f32 = 256.0;
+/// how far the aim cursor can get from the player, in pixels
+const AIM_RADIUS: f32 = 64.0;
+
+/// the device last used, decides where `CursorPosition` comes from
+#[derive(Resource, Default, Clone, Copy, Eq, PartialEq, Debug)]
+pub enum InputDevice {
+    #[default]
+    KeyboardMouse,
+    Gamepad,
+}
+
+#[derive(Resource, Default)]
+pub struct GamepadInput {
+    pub gamepad: Option<Gamepad>,
+    /// left stick, each axis from -1 to 1
+    pub movement: Vec2,
+    /// offset of the virtual aim cursor from the player, moved by the right stick
+    pub aim: Vec2,
+}
+
+pub fn update_gamepad(
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut gamepad_input: ResMut<GamepadInput>,
+    mut input_device: ResMut<InputDevice>,
+    time: Res<Time>,
+) {
+    let mouse_moved = mouse_motion.iter().any(|ev| ev.delta != Vec2::ZERO);
+    if mouse_moved || keyboard_input.get_just_pressed().next().is_some() || mouse_input.get_just_pressed().next().is_some() {
+        *input_device = InputDevice::KeyboardMouse;
+    }
+
+    // stick with the current gamepad while it is connected
+    if !gamepad_input.gamepad.is_some_and(|gamepad| gamepads.contains(gamepad)) {
+        gamepad_input.gamepad = gamepads.iter().next();
+    }
+    let Some(gamepad) = gamepad_input.gamepad else {
+        gamepad_input.movement = Vec2::ZERO;
+        *input_device = InputDevice::KeyboardMouse;
+        return;
+    };
+
+    let stick = |x, y| {
+        let value = Vec2::new(
+            axes.get(GamepadAxis::new(gamepad, x)).unwrap_or(0.0),
+            axes.get(GamepadAxis::new(gamepad, y)).unwrap_or(0.0),
+        );
+        if value.length() < STICK_DEADZONE { Vec2::ZERO } else { value }
+    };
+    let movement = stick(GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY);
+    let aim = stick(GamepadAxisType::RightStickX, GamepadAxisType::RightStickY);
+
+    let button_pressed = gamepad_buttons.get_just_pressed().any(|button| button.gamepad == gamepad);
+    if button_pressed || movement != Vec2::ZERO || aim != Vec2::ZERO {
+        *input_device = InputDevice::Gamepad;
+    }
+
+    gamepad_input.movement = movement;
+    gamepad_input.aim = (gamepad_input.aim + aim * AIM_SPEED * time.delta_seconds()).clamp_length_max(AIM_RADIUS);
+}
@@ -0,0 +1,354 @@
+use std::{collections::BTreeMap, fs, io};
+
+use bevy::{input::InputSystem, prelude::*};
+use serde::{Deserialize, Serialize};
+
+pub mod gamepad;
+pub mod settings;
+
+const CONFIG_DIR: &str = "./config";
+const KEYBINDINGS_PATH: &str = "./config/keybindings.ron";
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Keybindings::load());
+        app.init_resource::<Input<Action>>();
+        app.init_resource::<settings::SettingsMenu>();
+        app.init_resource::<gamepad::InputDevice>();
+        app.init_resource::<gamepad::GamepadInput>();
+
+        app.add_systems(PreUpdate, (gamepad::update_gamepad, update_actions).chain().after(InputSystem));
+        app.add_systems(Update, (settings::toggle_settings, settings::settings_ui).chain());
+    }
+}
+
+/// everything the player can do with a key or mouse button,
+/// systems read these from `Input<Action>` instead of raw key codes
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    /// only used while flying
+    MoveUp,
+    /// only used while flying
+    MoveDown,
+    Jump,
+    Mine,
+    Place,
+    Attack,
+    Interact,
+    WallMode,
+    Inventory,
+    HotbarNext,
+    HotbarPrev,
+    Hotbar1,
+    Hotbar2,
+    Hotbar3,
+    Hotbar4,
+    Hotbar5,
+    Hotbar6,
+    Hotbar7,
+    Hotbar8,
+    Hotbar9,
+    Hotbar10,
+    ZoomIn,
+    ZoomOut,
+    ZoomReset,
+    /// held to zoom with the mouse wheel instead of scrolling the hotbar
+    WheelZoom,
+    Screenshot,
+    ToggleFly,
+    Settings,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: [Action; 31] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Jump,
+        Action::Mine,
+        Action::Place,
+        Action::Attack,
+        Action::Interact,
+        Action::WallMode,
+        Action::Inventory,
+        Action::HotbarNext,
+        Action::HotbarPrev,
+        Action::Hotbar1,
+        Action::Hotbar2,
+        Action::Hotbar3,
+        Action::Hotbar4,
+        Action::Hotbar5,
+        Action::Hotbar6,
+        Action::Hotbar7,
+        Action::Hotbar8,
+        Action::Hotbar9,
+        Action::Hotbar10,
+        Action::ZoomIn,
+        Action::ZoomOut,
+        Action::ZoomReset,
+        Action::WheelZoom,
+        Action::Screenshot,
+        Action::ToggleFly,
+        Action::Settings,
+        Action::Quit,
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Action::MoveLeft => "move left",
+            Action::MoveRight => "move right",
+            Action::MoveUp => "fly up",
+            Action::MoveDown => "fly down",
+            Action::Jump => "jump",
+            Action::Mine => "mine",
+            Action::Place => "place",
+            Action::Attack => "attack",
+            Action::Interact => "interact",
+            Action::WallMode => "wall mode",
+            Action::Inventory => "inventory",
+            Action::HotbarNext => "next hotbar slot",
+            Action::HotbarPrev => "previous hotbar slot",
+            Action::Hotbar1 => "hotbar slot 1",
+            Action::Hotbar2 => "hotbar slot 2",
+            Action::Hotbar3 => "hotbar slot 3",
+            Action::Hotbar4 => "hotbar slot 4",
+            Action::Hotbar5 => "hotbar slot 5",
+            Action::Hotbar6 => "hotbar slot 6",
+            Action::Hotbar7 => "hotbar slot 7",
+            Action::Hotbar8 => "hotbar slot 8",
+            Action::Hotbar9 => "hotbar slot 9",
+            Action::Hotbar10 => "hotbar slot 10",
+            Action::ZoomIn => "zoom in",
+            Action::ZoomOut => "zoom out",
+            Action::ZoomReset => "reset zoom",
+            Action::WheelZoom => "zoom with wheel",
+            Action::Screenshot => "screenshot",
+            Action::ToggleFly => "toggle fly",
+            Action::Settings => "settings",
+            Action::Quit => "save and quit",
+        }
+    }
+
+    pub fn default_binding(&self) -> Binding {
+        match self {
+            Action::MoveLeft => Binding::Key(KeyCode::A),
+            Action::MoveRight => Binding::Key(KeyCode::D),
+            Action::MoveUp => Binding::Key(KeyCode::W),
+            Action::MoveDown => Binding::Key(KeyCode::S),
+            Action::Jump => Binding::Key(KeyCode::Space),
+            Action::Mine => Binding::Mouse(MouseButton::Left),
+            Action::Place => Binding::Mouse(MouseButton::Right),
+            Action::Attack => Binding::Mouse(MouseButton::Left),
+            Action::Interact => Binding::Mouse(MouseButton::Right),
+            Action::WallMode => Binding::Key(KeyCode::ShiftLeft),
+            Action::Inventory => Binding::Key(KeyCode::E),
+            Action::HotbarNext => Binding::Key(KeyCode::BracketRight),
+            Action::HotbarPrev => Binding::Key(KeyCode::BracketLeft),
+            Action::Hotbar1 => Binding::Key(KeyCode::Key1),
+            Action::Hotbar2 => Binding::Key(KeyCode::Key2),
+            Action::Hotbar3 => Binding::Key(KeyCode::Key3),
+            Action::Hotbar4 => Binding::Key(KeyCode::Key4),
+            Action::Hotbar5 => Binding::Key(KeyCode::Key5),
+            Action::Hotbar6 => Binding::Key(KeyCode::Key6),
+            Action::Hotbar7 => Binding::Key(KeyCode::Key7),
+            Action::Hotbar8 => Binding::Key(KeyCode::Key8),
+            Action::Hotbar9 => Binding::Key(KeyCode::Key9),
+            Action::Hotbar10 => Binding::Key(KeyCode::Key0),
+            Action::ZoomIn => Binding::Key(KeyCode::Equals),
+            Action::ZoomOut => Binding::Key(KeyCode::Minus),
+            Action::ZoomReset => Binding::Key(KeyCode::Back),
+            Action::WheelZoom => Binding::Key(KeyCode::ControlLeft),
+            Action::Screenshot => Binding::Key(KeyCode::F2),
+            Action::ToggleFly => Binding::Key(KeyCode::F3),
+            Action::Settings => Binding::Key(KeyCode::F1),
+            Action::Quit => Binding::Key(KeyCode::Escape),
+        }
+    }
+
+    /// fixed gamepad layout, movement and aiming go through the sticks instead
+    pub fn gamepad_button(&self) -> Option<GamepadButtonType> {
+        match self {
+            Action::Jump => Some(GamepadButtonType::South),
+            Action::Mine => Some(GamepadButtonType::RightTrigger2),
+            Action::Attack => Some(GamepadButtonType::RightTrigger2),
+            Action::Place => Some(GamepadButtonType::LeftTrigger2),
+            Action::Interact => Some(GamepadButtonType::LeftTrigger2),
+            Action::WallMode => Some(GamepadButtonType::West),
+            Action::Inventory => Some(GamepadButtonType::North),
+            Action::HotbarNext => Some(GamepadButtonType::RightTrigger),
+            Action::HotbarPrev => Some(GamepadButtonType::LeftTrigger),
+            Action::Settings => Some(GamepadButtonType::Start),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    fn pressed(&self, keyboard_input: &Input<KeyCode>, mouse_input: &Input<MouseButton>) -> bool {
+        match self {
+            Binding::Key(key) => keyboard_input.pressed(*key),
+            Binding::Mouse(button) => mouse_input.pressed(*button),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("mouse {:?}", button),
+        }
+    }
+}
+
+/// which key or mouse button triggers each action, stored in `KEYBINDINGS_PATH`
+#[derive(Resource)]
+pub struct Keybindings(pub BTreeMap<Action, Binding>);
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self(Action::ALL.iter().map(|action| (*action, action.default_binding())).collect())
+    }
+}
+
+impl Keybindings {
+    /// reads the config file, falling back to the default for anything missing
+    pub fn load() -> Self {
+        let mut keybindings = Self::default();
+        match fs::read_to_string(KEYBINDINGS_PATH) {
+            Ok(text) => match ron::from_str::<BTreeMap<Action, Binding>>(&text) {
+                Ok(loaded) => keybindings.0.extend(loaded),
+                Err(err) => warn!("could not parse {}, using default keybindings: {}", KEYBINDINGS_PATH, err),
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => warn!("could not read {}, using default keybindings: {}", KEYBINDINGS_PATH, err),
+        }
+        keybindings
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(&self.0, ron::ser::PrettyConfig::default())
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        fs::create_dir_all(CONFIG_DIR)?;
+        fs::write(KEYBINDINGS_PATH, text)
+    }
+
+    pub fn get(&self, action: Action) -> Binding {
+        self.0.get(&action).copied().unwrap_or_else(|| action.default_binding())
+    }
+}
+
+/// translates raw keyboard, mouse and gamepad input into actions,
+/// nothing is pressed while the settings menu waits for a new binding,
+/// and the input it captured does nothing until it is let go
+pub fn update_actions(
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_input: Res<gamepad::GamepadInput>,
+    keybindings: Res<Keybindings>,
+    mut settings_menu: ResMut<settings::SettingsMenu>,
+    mut actions: ResMut<Input<Action>>,
+) {
+    actions.clear();
+    if settings_menu.rebinding.is_some() {
+        actions.release_all();
+        return;
+    }
+    if settings_menu.captured.is_some_and(|binding| !binding.pressed(&keyboard_input, &mouse_input)) {
+        settings_menu.captured = None;
+    }
+
+    for action in Action::ALL {
+        let binding = keybindings.get(action);
+        let key_pressed = settings_menu.captured != Some(binding) && binding.pressed(&keyboard_input, &mouse_input);
+        let gamepad_pressed = gamepad_input.gamepad.zip(action.gamepad_button())
+            .is_some_and(|(gamepad, button)| gamepad_buttons.pressed(GamepadButton::new(gamepad, button)));
+        if key_pressed || gamepad_pressed {
+            actions.press(action);
+        } else {
+            actions.release(action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Input<KeyCode>>();
+        app.init_resource::<Input<MouseButton>>();
+        app.init_resource::<Input<GamepadButton>>();
+        app.init_resource::<gamepad::GamepadInput>();
+        app.init_resource::<settings::SettingsMenu>();
+        app.init_resource::<Input<Action>>();
+        app.insert_resource(Keybindings::default());
+        app.add_systems(Update, update_actions);
+        app
+    }
+
+    fn press(app: &mut App, key: KeyCode) {
+        app.world.resource_mut::<Input<KeyCode>>().press(key);
+    }
+
+    fn release(app: &mut App, key: KeyCode) {
+        app.world.resource_mut::<Input<KeyCode>>().release(key);
+    }
+
+    #[test]
+    fn nothing_is_pressed_while_rebinding() {
+        let mut app = app();
+        app.world.resource_mut::<settings::SettingsMenu>().rebinding = Some(Action::Jump);
+        press(&mut app, KeyCode::Escape);
+        app.update();
+        assert!(!app.world.resource::<Input<Action>>().pressed(Action::Quit));
+    }
+
+    #[test]
+    fn captured_input_is_ignored_until_released() {
+        let mut app = app();
+        // escape was just bound to jump and is still held, it also quits
+        app.world.resource_mut::<Keybindings>().0.insert(Action::Jump, Binding::Key(KeyCode::Escape));
+        app.world.resource_mut::<settings::SettingsMenu>().captured = Some(Binding::Key(KeyCode::Escape));
+        press(&mut app, KeyCode::Escape);
+        app.update();
+        let actions = app.world.resource::<Input<Action>>();
+        assert!(!actions.pressed(Action::Jump));
+        assert!(!actions.pressed(Action::Quit));
+
+        release(&mut app, KeyCode::Escape);
+        app.update();
+        assert_eq!(app.world.resource::<settings::SettingsMenu>().captured, None);
+
+        press(&mut app, KeyCode::Escape);
+        app.update();
+        let actions = app.world.resource::<Input<Action>>();
+        assert!(actions.just_pressed(Action::Jump));
+        assert!(actions.just_pressed(Action::Quit));
+    }
+
+    #[test]
+    fn hotbar_slots_follow_their_bindings() {
+        let mut app = app();
+        app.world.resource_mut::<Keybindings>().0.insert(Action::Hotbar3, Binding::Key(KeyCode::Q));
+        press(&mut app, KeyCode::Q);
+        press(&mut app, KeyCode::Key1);
+        app.update();
+        let actions = app.world.resource::<Input<Action>>();
+        assert!(actions.just_pressed(Action::Hotbar3));
+        assert!(actions.just_pressed(Action::Hotbar1));
+        assert!(!actions.pressed(Action::Hotbar2));
+    }
+}
@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+
+use super::{Action, Binding, Keybindings};
+
+#[derive(Resource, Default)]
+pub struct SettingsMenu {
+    pub open: bool,
+    /// action waiting for the next key or mouse press to become its binding
+    pub rebinding: Option<Action>,
+    /// input that was just bound, it is still held and must not trigger anything until released
+    pub captured: Option<Binding>,
+}
+
+pub fn toggle_settings(
+    actions: Res<Input<Action>>,
+    mut settings_menu: ResMut<SettingsMenu>,
+) {
+    if actions.just_pressed(Action::Settings) {
+        settings_menu.open = !settings_menu.open;
+    }
+}
+
+pub fn settings_ui(
+    mut contexts: EguiContexts,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut keybindings: ResMut<Keybindings>,
+    mut settings_menu: ResMut<SettingsMenu>,
+) {
+    if !settings_menu.open {
+        settings_menu.rebinding = None;
+        return;
+    }
+
+    if let Some(action) = settings_menu.rebinding {
+        let pressed = keyboard_input.get_just_pressed().next().map(|key| Binding::Key(*key))
+            .or_else(|| mouse_input.get_just_pressed().next().map(|button| Binding::Mouse(*button)));
+        if let Some(binding) = pressed {
+            keybindings.0.insert(action, binding);
+            settings_menu.rebinding = None;
+            settings_menu.captured = Some(binding);
+            if let Err(err) = keybindings.save() {
+                warn!("could not save keybindings: {}", err);
+            }
+        }
+    }
+
+    let mut open = settings_menu.open;
+    let mut clicked = None;
+    let mut reset = false;
+    egui::Window::new("settings").open(&mut open).show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("keybindings").show(ui, |ui| {
+            for action in Action::ALL {
+                ui.label(action.name());
+                let text = match settings_menu.rebinding {
+                    Some(rebinding) if rebinding == action => "press a key...".to_string(),
+                    _ => keybindings.get(action).name(),
+                };
+                if ui.button(text).clicked() {
+                    clicked = Some(action);
+                }
+                ui.end_row();
+            }
+        });
+        if ui.button("reset to defaults").clicked() {
+            reset = true;
+        }
+    });
+
+    settings_menu.open = open;
+    if clicked.is_some() {
+        settings_menu.rebinding = clicked;
+    }
+    if reset {
+        *keybindings = Keybindings::default();
+        settings_menu.rebinding = None;
+        if let Err(err) = keybindings.save() {
+            warn!("could not save keybindings: {}", err);
+        }
+    }
+}
@@ -3,7 +3,7 @@ use bevy_egui::{EguiContexts, egui};
 use chrono::prelude::*;
 use std::fs;
 
-use crate::{physics::Velocity, player::{player::Player, camera::CursorPosition}, world::chunks::Colls, entities::item::Item};
+use crate::{controls::Action, physics::{Collider, NoClip, Rigidbody, Velocity}, player::{player::Player, camera::CursorPosition}, world::{block::BlockShape, chunks::Colls, storage::WorldStorage}, entities::item::Item};
 
 #[allow(dead_code)]
 pub fn chunk_borders(
@@ -21,29 +21,44 @@ pub fn chunk_borders(
 #[allow(dead_code)]
 pub fn draw_colls(
     mut gizmos: Gizmos,
-    colls: Res<Colls>
+    colls: Res<Colls>,
+    collider_query: Query<(&Transform, &Collider)>,
 ) {
+    for (transform, collider) in collider_query.iter() {
+        gizmos.rect_2d(collider.center(transform.translation.truncate()), 0.0, collider.size, Color::RED);
+    }
+
     for (_, hashset) in colls.0.iter() {
-        for (pos, len) in hashset.iter() {
-            let npos = Vec2 {
-                x: pos.x as f32 * 8.0 + *len as f32 * 4.0 - 4.0,
-                y: pos.y as f32 * 8.0,
-            };
-            gizmos.rect_2d(npos, 0.0, vec2(8.0 * *len as f32, 8.0), Color::GREEN);
-            // gizmos.rect_2d(pos.as_vec2() * 8.0, 0.0, vec2(8.0 * *len as f32, 8.0), Color::GREEN);
+        for (pos, len, shape) in hashset.iter() {
+            let left = pos.x as f32 * 8.0 - 4.0;
+            let bottom = pos.y as f32 * 8.0 - 4.0;
+            let width = 8.0 * *len as f32;
+            match shape {
+                BlockShape::Platform => gizmos.line_2d(vec2(left, bottom + 8.0), vec2(left + width, bottom + 8.0), Color::GREEN),
+                BlockShape::SlopeUpRight => gizmos.line_2d(vec2(left, bottom), vec2(left + 8.0, bottom + 8.0), Color::GREEN),
+                BlockShape::SlopeUpLeft => gizmos.line_2d(vec2(left, bottom + 8.0), vec2(left + 8.0, bottom), Color::GREEN),
+                _ => {
+                    let height = shape.height(0.0, 8.0);
+                    gizmos.rect_2d(vec2(left + width / 2.0, bottom + height / 2.0), 0.0, vec2(width, height), Color::GREEN);
+                }
+            }
         }
     }
 }
 
 pub fn debug_text(
     mut contexts: EguiContexts,
-    player_query: Query<(&Transform, &Velocity, &Player)>,
-    item_query: Query<With<Item>>,
+    player_query: Query<(&Transform, &Velocity, &Rigidbody), With<Player>>,
+    item_query: Query<&Item>,
     colls: Res<Colls>,
+    world_storage: Res<WorldStorage>,
     time: Res<Time>,
     cursor_pos: Res<CursorPosition>
 ) {
-    let (transform, velocity, _player) = player_query.single();
+    let (transform, velocity, rigidbody) = player_query.single();
+    // what a straight line from the player towards the cursor runs into
+    let position = transform.translation.truncate();
+    let ray = world_storage.cast_ray(position, cursor_pos.0.as_vec2() * 8.0 - position, 256.0);
     let mut coll_count = 0;
     for (_, hashset) in colls.0.iter() {
         coll_count += hashset.len();
@@ -54,18 +69,23 @@ pub fn debug_text(
         ui.label(format!("wpos: {}", transform.translation.truncate().floor()));
         ui.label(format!("cpos: {}", cursor_pos.0));
         ui.label(format!("vel: {}", velocity.0));
+        ui.label(format!("contact: {}", rigidbody.contact));
+        if let Some(hit) = ray {
+            ui.label(format!("ray: {} {} {:.1}px", hit.block_pos, hit.normal, hit.distance));
+        }
         ui.label(format!("col: {}", coll_count));
-        ui.label(format!("items: {}", item_query.iter().count()));
+        ui.label(format!("items: {} ({} total)", item_query.iter().count(), item_query.iter().map(|item| item.0.count).sum::<u32>()));
+        ui.label(format!("mem: {}kb", world_storage.heap_size() / 1024));
     });
 }
 
 pub fn screenshot(
-    keyboard_input: Res<Input<KeyCode>>,
+    actions: Res<Input<Action>>,
     main_window: Query<Entity, With<PrimaryWindow>>,
     mut screenshot_manager: ResMut<ScreenshotManager>,
     mut counter: bevy::prelude::Local<u32>
 ) {
-    if keyboard_input.just_pressed(KeyCode::F2) {
+    if actions.just_pressed(Action::Screenshot) {
         let _ = fs::create_dir_all("./screenshots");
         let local: DateTime<chrono::Local> = chrono::Local::now();
         let formatted_date_time = local.format("%Y-%m-%d_%H:%M:%S");
@@ -75,4 +95,20 @@ pub fn screenshot(
             .save_screenshot_to_disk(main_window.single(), path)
             .unwrap();
     }
-}
\ No newline at end of file
+}
+
+/// toggles flying through blocks for the player
+pub fn toggle_fly(
+    mut commands: Commands,
+    actions: Res<Input<Action>>,
+    mut player_query: Query<(Entity, &mut Velocity, Option<&NoClip>), With<Player>>,
+) {
+    if !actions.just_pressed(Action::ToggleFly) { return; };
+    let (entity, mut velocity, noclip) = player_query.single_mut();
+    velocity.0 = Vec2::ZERO;
+    if noclip.is_some() {
+        commands.entity(entity).remove::<NoClip>();
+    } else {
+        commands.entity(entity).insert(NoClip);
+    }
+}
@@ -1,19 +1,35 @@
-use std::ops::Div;
+use bevy::{prelude::*, math::vec2, utils::HashSet};
 
-use bevy::{prelude::*, math::{vec3, ivec2}, utils::HashSet};
+use crate::{items::ItemStack, physics::{contacts::{CollisionEvent, CollisionLayers}, Collider, PhysicsPosition, Rigidbody, Velocity}, world::{storage::WorldStorage, chunks::ReloadChunk, block::Block, position::{tile_pos, ChunkPos}}};
 
-use crate::{physics::{Rigidbody, Velocity}, world::{storage::WorldStorage, chunks::ReloadChunk, block::Block, position::ChunkPos}};
+use super::{health::Health, item::SpawnItem};
 
-use super::item::SpawnItem;
+/// arrows that land blow up the tiles within this many pixels
+const EXPLOSION_RADIUS: f32 = 16.0;
+/// seconds before an arrow stuck in a wall or ceiling despawns
+const STUCK_LIFETIME: f32 = 60.0;
 
 #[derive(Event)]
 pub struct SpawnArrow {
     pub position: Vec2,
-    pub velocity: Vec2
+    pub velocity: Vec2,
+    pub damage: f32,
 }
 
 #[derive(Component)]
-pub struct Arrow;
+pub struct Arrow {
+    pub damage: f32,
+}
+
+/// an arrow that hit a tile and no longer moves, `landed` when it came down on top of one
+#[derive(Component)]
+pub struct Stuck {
+    pub landed: bool,
+    /// the tile holding the arrow
+    pub tile: IVec2,
+    /// seconds since it got stuck
+    pub age: f32,
+}
 
 pub fn spawn(mut commands: Commands, mut item_event: EventReader<SpawnArrow>) {
     for ev in item_event.iter() {
@@ -21,74 +37,226 @@ pub fn spawn(mut commands: Commands, mut item_event: EventReader<SpawnArrow>) {
             SpriteBundle {
                 sprite: Sprite {
                     color: Color::YELLOW,
+                    custom_size: Some(vec2(2.0, 8.0)),
                     ..default()
                 },
-                transform: Transform {
-                    translation: ev.position.extend(20.0),
-                    scale: vec3(2.0, 8.0, 1.0),
-                    ..default()
-                },
+                transform: Transform::from_translation(ev.position.extend(20.0)),
                 ..default()
             },
-            Rigidbody {
-                grounded: false,
-                friction: true
-            },
-            Arrow,
+            Rigidbody::default(),
+            // the sprite rotates with the flight direction, so collide as a small square
+            Collider::new(Vec2::splat(2.0)),
+            CollisionLayers::new(CollisionLayers::PROJECTILE, CollisionLayers::MOB),
+            Arrow { damage: ev.damage },
             Velocity(ev.velocity),
         ));
     }
 }
 
 pub fn rotate(
-    mut q: Query<(&mut Transform, &Velocity, &Rigidbody), With<Arrow>>
+    mut q: Query<(&mut Transform, &Velocity), (With<Arrow>, Without<Stuck>)>
 ) {
-    for (mut transform, velocity, rigidbody) in q.iter_mut() {
-        if !rigidbody.grounded {
-            let angle = velocity.0.x.atan2(velocity.0.y);
-            transform.rotation = Quat::from_rotation_z(-angle);
+    for (mut transform, velocity) in q.iter_mut() {
+        let angle = velocity.0.x.atan2(velocity.0.y);
+        transform.rotation = Quat::from_rotation_z(-angle);
+    }
+}
+
+/// stops arrows on the physics step they first touch a tile, before they can bounce or slide.
+/// without a rigidbody nothing moves them anymore
+pub fn stick(
+    mut commands: Commands,
+    mut q: Query<(Entity, &mut Velocity, &Rigidbody, &PhysicsPosition, &Collider), (With<Arrow>, Without<Stuck>)>,
+) {
+    for (entity, mut velocity, rigidbody, position, collider) in q.iter_mut() {
+        if rigidbody.contact == IVec2::ZERO { continue; };
+
+        // the contact normal points out of the tile, just behind the arrow's tip
+        let tip = collider.center(position.current) - rigidbody.contact.as_vec2() * (collider.size / 2.0 + 1.0);
+        velocity.0 = Vec2::ZERO;
+        commands.entity(entity)
+            .remove::<Rigidbody>()
+            .insert(Stuck { landed: rigidbody.grounded, tile: tile_pos(tip), age: 0.0 });
+    }
+}
+
+/// arrows stuck in a wall or ceiling despawn once the tile holding them is gone, or after
+/// `STUCK_LIFETIME` so they never pile up
+pub fn despawn_stuck(
+    mut commands: Commands,
+    mut q: Query<(Entity, &mut Stuck), With<Arrow>>,
+    world_storage: Res<WorldStorage>,
+    time: Res<Time>,
+) {
+    for (entity, mut stuck) in q.iter_mut() {
+        // landed ones blow up in `check_collisions`
+        if stuck.landed { continue; };
+
+        stuck.age += time.delta_seconds();
+        if stuck.age > STUCK_LIFETIME || !world_storage.is_solid(stuck.tile) {
+            commands.entity(entity).despawn_recursive();
         }
     }
 }
 
+/// flying arrows damage the first entity with health they run into
+pub fn hit(
+    mut commands: Commands,
+    mut collision_event: EventReader<CollisionEvent>,
+    arrow_query: Query<&Arrow, Without<Stuck>>,
+    mut target_query: Query<&mut Health>,
+    mut spent: Local<HashSet<Entity>>,
+) {
+    spent.clear();
+    for ev in collision_event.iter() {
+        let CollisionEvent::Enter(a, b) = *ev else { continue; };
+        let (arrow_entity, target) = if arrow_query.contains(a) { (a, b) } else { (b, a) };
+        let Ok(arrow) = arrow_query.get(arrow_entity) else { continue; };
+        if spent.contains(&arrow_entity) { continue; };
+        let Ok(mut health) = target_query.get_mut(target) else { continue; };
+
+        health.0 -= arrow.damage;
+        spent.insert(arrow_entity);
+        commands.entity(arrow_entity).despawn_recursive();
+    }
+}
+
+/// arrows that land blow up, ones stuck in a wall or ceiling stay there
 pub fn check_collisions(
     mut commands: Commands,
-    q: Query<(Entity, &Rigidbody, &Transform), With<Arrow>>,
+    q: Query<(Entity, &Stuck, &Transform), With<Arrow>>,
     mut world_storage: ResMut<WorldStorage>,
     mut reload_event: EventWriter<ReloadChunk>,
     mut item_event: EventWriter<SpawnItem>,
 ) {
-    for (entity, rigidbody, transform) in q.iter() {
-        if !rigidbody.grounded { continue; };
+    for (entity, stuck, transform) in q.iter() {
+        if !stuck.landed { continue; };
 
-        let block_pos = transform.translation.truncate().div(8.0).as_ivec2();
         commands.entity(entity).despawn_recursive();
 
         let mut chunks_to_reload: HashSet<ChunkPos> = HashSet::new();
 
-        for y in block_pos.y-2..block_pos.y+2 {
-            for x in block_pos.x-2..block_pos.x+2 {
-                let pos = ivec2(x, y);
-                let block = world_storage.get_block(pos).unwrap();
-                if block == Block::Air {
-                    continue;
-                };
-                let chunk_pos = ChunkPos::from_block_pos(pos);
+        for pos in world_storage.overlap_circle(transform.translation.truncate(), EXPLOSION_RADIUS) {
+            let Some(block) = world_storage.get_block(pos) else { continue; };
+            if block.hardness().is_infinite() { continue; };
+            let chunk_pos = ChunkPos::from_block_pos(pos);
 
+            if let Some(item) = block.drop_item() {
                 item_event.send(SpawnItem {
                     position: Vec2 {
                         x: (pos.x * 8) as f32,
                         y: (pos.y * 8) as f32,
                     },
-                    block, // hehe crash here!, fix l8r B)
+                    stack: ItemStack::new(item, 1),
                 });
-                world_storage.set_block(pos, Block::Air);
-                chunks_to_reload.insert(chunk_pos);
             }
+            world_storage.set_block(pos, Block::Air);
+            chunks_to_reload.insert(chunk_pos);
         }
-        
+
         for chunk_pos in chunks_to_reload.iter() {
             reload_event.send(ReloadChunk(*chunk_pos));
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec2;
+
+    use crate::{physics::{contacts::find_contacts, PhysicsPlugin, PhysicsPosition}, states::GameState};
+
+    use super::*;
+
+    /// a stone floor along y = 0 and a wall in column 10, whose left face is at x = 76
+    fn app() -> App {
+        let mut world_storage = WorldStorage::new();
+        for x in 0..20 {
+            world_storage.set_block(ivec2(x, 0), Block::Stone);
+        }
+        for y in 0..20 {
+            world_storage.set_block(ivec2(10, y), Block::Stone);
+        }
+
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin);
+        app.insert_resource(State::new(GameState::InGame));
+        app.insert_resource(world_storage);
+        app.add_systems(FixedUpdate, stick.after(find_contacts));
+        app.init_resource::<Time>();
+        app.add_systems(Update, despawn_stuck);
+        app
+    }
+
+    fn shoot(app: &mut App, position: Vec2, velocity: Vec2) -> Entity {
+        app.world.spawn((
+            Transform::from_translation(position.extend(0.0)),
+            PhysicsPosition { current: position, previous: position },
+            Rigidbody::default(),
+            Collider::new(Vec2::splat(2.0)),
+            Arrow { damage: 1.0 },
+            Velocity(velocity),
+        )).id()
+    }
+
+    fn step(app: &mut App, steps: usize) {
+        for _ in 0..steps {
+            app.world.run_schedule(FixedUpdate);
+        }
+    }
+
+    #[test]
+    fn sticks_in_walls() {
+        let mut app = app();
+        let arrow = shoot(&mut app, Vec2::new(40.0, 60.0), Vec2::new(256.0, 0.0));
+        step(&mut app, 30);
+
+        let entity = app.world.entity(arrow);
+        assert!(!entity.get::<Stuck>().unwrap().landed);
+        assert_eq!(entity.get::<Stuck>().unwrap().tile, ivec2(10, 7));
+        assert!(!entity.contains::<Rigidbody>());
+        assert_eq!(entity.get::<Velocity>().unwrap().0, Vec2::ZERO);
+        let position = entity.get::<PhysicsPosition>().unwrap().current;
+        assert!((position.x - 75.0).abs() < 0.001, "{}", position.x);
+
+        // gravity no longer pulls it down the wall
+        step(&mut app, 30);
+        assert_eq!(app.world.get::<PhysicsPosition>(arrow).unwrap().current, position);
+    }
+
+    #[test]
+    fn lands_on_the_ground() {
+        let mut app = app();
+        let arrow = shoot(&mut app, Vec2::new(40.0, 40.0), Vec2::new(0.0, -100.0));
+        step(&mut app, 60);
+
+        assert!(app.world.get::<Stuck>(arrow).unwrap().landed);
+        let position = app.world.get::<PhysicsPosition>(arrow).unwrap().current;
+        assert_eq!(position.x, 40.0);
+        assert!((position.y - 5.0).abs() < 0.001, "{}", position.y);
+    }
+
+    #[test]
+    fn stuck_arrows_go_with_their_tile() {
+        let mut app = app();
+        let arrow = shoot(&mut app, Vec2::new(40.0, 60.0), Vec2::new(256.0, 0.0));
+        step(&mut app, 30);
+        app.world.run_schedule(Update);
+        assert!(app.world.get_entity(arrow).is_some());
+
+        app.world.resource_mut::<WorldStorage>().set_block(ivec2(10, 7), Block::Air);
+        app.world.run_schedule(Update);
+        assert!(app.world.get_entity(arrow).is_none());
+    }
+
+    #[test]
+    fn stuck_arrows_despawn_after_a_while() {
+        let mut app = app();
+        let arrow = shoot(&mut app, Vec2::new(40.0, 60.0), Vec2::new(256.0, 0.0));
+        step(&mut app, 30);
+
+        app.world.get_mut::<Stuck>(arrow).unwrap().age = STUCK_LIFETIME + 1.0;
+        app.world.run_schedule(Update);
+        assert!(app.world.get_entity(arrow).is_none());
+    }
+}
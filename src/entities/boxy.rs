@@ -1,7 +1,9 @@
 use bevy::{math::vec3, prelude::*};
 use rand::prelude::*;
 
-use crate::physics::{Rigidbody, Velocity};
+use crate::physics::{contacts::CollisionLayers, Collider, Rigidbody, Velocity};
+
+use super::health::Health;
 
 #[allow(dead_code)]
 pub fn spawn(mut commands: Commands) {
@@ -9,20 +11,20 @@ pub fn spawn(mut commands: Commands) {
         SpriteBundle {
             sprite: Sprite {
                 color: Color::ORANGE,
+                custom_size: Some(Vec2::splat(8.0)),
                 ..default()
             },
             transform: Transform {
                 translation: vec3(150.0, 7.0 * 32.0 * 8.0 + 100.0, 20.0),
-                scale: vec3(8.0, 8.0, 8.0),
                 ..default()
             },
             ..default()
         },
-        Rigidbody {
-            grounded: false,
-            friction: false
-        },
+        Rigidbody::default(),
+        Collider::new(Vec2::splat(8.0)),
+        CollisionLayers::new(CollisionLayers::MOB, CollisionLayers::ALL),
         Velocity(Vec2::ZERO),
+        Health(10.0),
     ));
 }
 
@@ -40,16 +42,15 @@ pub fn stress(mut commands: Commands) {
                 },
                 transform: Transform {
                     translation: vec3(x, 7.0 * 32.0 * 8.0 + y, 20.0),
-                    scale: vec3(8.0, 8.0, 8.0),
-                    ..default()
+                        ..default()
                 },
                 ..default()
             },
-            Rigidbody {
-                grounded: false,
-                friction: false
-            },
+            Rigidbody::default(),
+            Collider::new(Vec2::splat(8.0)),
+            CollisionLayers::new(CollisionLayers::MOB, CollisionLayers::ALL),
             Velocity(Vec2::ZERO),
+            Health(10.0),
         ));
     }
 }
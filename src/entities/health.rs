@@ -0,0 +1,16 @@
+use bevy::prelude::*;
+
+/// entities that can be hurt by weapons, despawned once it drops to zero
+#[derive(Component)]
+pub struct Health(pub f32);
+
+pub fn despawn_dead(
+    mut commands: Commands,
+    health_query: Query<(Entity, &Health)>,
+) {
+    for (entity, health) in health_query.iter() {
+        if health.0 <= 0.0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
@@ -1,19 +1,53 @@
-use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy::{prelude::*, utils::HashSet};
 use rand::prelude::*;
 
 use crate::{
-    physics::{Rigidbody, Velocity},
-    world::block::Block, player::player::Player,
+    physics::{contacts::{CollisionLayers, Contacts}, Collider, PhysicsMaterial, PhysicsPosition, Rigidbody, Velocity},
+    items::ItemStack, player::inventory::Inventory,
 };
 
+/// size of a dropped item, in pixels
+const ITEM_SIZE: f32 = 4.0;
+
 #[derive(Event)]
 pub struct SpawnItem {
     pub position: Vec2,
-    pub block: Block,
+    pub stack: ItemStack,
 }
 
+/// entities that pick up dropped items they touch into their `Inventory`
+#[derive(Component)]
+pub struct Collector;
+
+/// a dropped item and what a collector gets when picking it up
 #[derive(Component)]
-pub struct Item;
+pub struct Item(pub ItemStack);
+
+/// seconds since the item was dropped
+#[derive(Component)]
+pub struct ItemAge(pub f32);
+
+#[derive(Resource)]
+pub struct DroppedItemSettings {
+    /// seconds before an item that was not picked up despawns
+    pub lifetime: f32,
+    /// identical items closer than this merge into one stack
+    pub merge_radius: f32,
+    /// items closer than this fly towards the nearest collector
+    pub magnet_radius: f32,
+    pub magnet_acceleration: f32,
+}
+
+impl Default for DroppedItemSettings {
+    fn default() -> Self {
+        Self {
+            lifetime: 300.0,
+            merge_radius: 12.0,
+            magnet_radius: 48.0,
+            magnet_acceleration: 1024.0,
+        }
+    }
+}
 
 #[derive(Component)]
 pub struct AnimationOffset(f32);
@@ -25,33 +59,24 @@ pub fn spawn(
 ) {
     let mut rng = thread_rng();
     for ev in item_event.iter() {
-        let texture_handle = asset_server.load(ev.block.texture_path());
+        let texture_handle = asset_server.load(ev.stack.item.icon_path());
         let entity = commands.spawn((
-            SpriteBundle {
-                sprite: Sprite {
-                    color: Color::NONE,
-                    ..default()
-                },
-                transform: Transform {
-                    translation: ev.position.extend(20.0),
-                    scale: Vec3::splat(4.0),
-                    ..default()
-                },
-                ..default()
-            },
-            Rigidbody {
-                grounded: false,
-                friction: true
-            },
-            Item,
+            SpatialBundle::from_transform(Transform::from_translation(ev.position.extend(20.0))),
+            Rigidbody::default(),
+            // slides a little and bounces off hard landings
+            PhysicsMaterial { friction: 0.6, restitution: 0.3, drag: 0.5 },
+            Collider::new(Vec2::splat(ITEM_SIZE)),
+            CollisionLayers::new(CollisionLayers::ITEM, CollisionLayers::PLAYER | CollisionLayers::MOB),
+            Item(ev.stack),
+            ItemAge(0.0),
             Velocity(Vec2::ZERO),
         )).id();
 
         let child = commands.spawn((
             SpriteBundle {
                 texture: texture_handle.clone(),
-                transform: Transform {
-                    scale: Vec3::splat(1.0/8.0), // NO IDEA DONT ASK
+                sprite: Sprite {
+                    custom_size: Some(Vec2::splat(ITEM_SIZE)),
                     ..default()
                 },
                 ..default()
@@ -63,26 +88,101 @@ pub fn spawn(
     }
 }
 
+/// collectors pick up the items they touch, whatever does not fit stays on the ground
 pub fn check_collisions(
     mut commands: Commands,
-    player_query: Query<&Transform, With<Player>>,
-    item_query: Query<(&Transform, Entity), With<Item>>,
+    contacts: Res<Contacts>,
+    mut collector_query: Query<&mut Inventory, With<Collector>>,
+    mut item_query: Query<(Entity, &mut Item)>,
 ) {
-    let player_transform = player_query.single();
-    for (item_transform, item_entity) in item_query.iter() {
-        let c = collide(
-            player_transform.translation,
-            player_transform.scale.truncate(),
-            item_transform.translation,
-            item_transform.scale.truncate()
-        );
-
-        if c.is_some() {
+    for (item_entity, mut item) in item_query.iter_mut() {
+        for other in contacts.touching(item_entity) {
+            let Ok(mut inventory) = collector_query.get_mut(other) else { continue; };
+
+            let leftover = inventory.add(item.0);
+            if leftover != item.0.count {
+                item.0.count = leftover;
+            }
+            if leftover == 0 { break; };
+        }
+
+        if item.0.count == 0 {
             commands.entity(item_entity).despawn_recursive();
         }
     }
 }
 
+/// merges nearby identical items into one entity, as long as the result fits in a stack
+pub fn merge(
+    mut commands: Commands,
+    mut item_query: Query<(Entity, &Transform, &mut Item, &mut ItemAge)>,
+    settings: Res<DroppedItemSettings>,
+) {
+    let items: Vec<(Entity, Vec2, ItemStack, f32)> = item_query.iter()
+        .map(|(entity, transform, item, age)| (entity, transform.translation.truncate(), item.0, age.0))
+        .collect();
+    let mut merged: HashSet<Entity> = HashSet::new();
+
+    for (i, (entity, position, stack, age)) in items.iter().enumerate() {
+        if merged.contains(entity) { continue; };
+        let mut count = stack.count;
+        let mut youngest = *age;
+
+        for (other_entity, other_position, other_stack, other_age) in items.iter().skip(i + 1) {
+            if merged.contains(other_entity) { continue; };
+            if other_stack.item != stack.item { continue; };
+            if count + other_stack.count > stack.item.max_stack() { continue; };
+            if position.distance(*other_position) > settings.merge_radius { continue; };
+
+            count += other_stack.count;
+            youngest = youngest.min(*other_age);
+            merged.insert(*other_entity);
+            commands.entity(*other_entity).despawn_recursive();
+        }
+
+        if count != stack.count {
+            let (_, _, mut item, mut age) = item_query.get_mut(*entity).unwrap();
+            item.0.count = count;
+            age.0 = youngest;
+        }
+    }
+}
+
+/// pulls items towards the nearest collector that has room for them, runs on the fixed step
+pub fn magnet(
+    collector_query: Query<(&PhysicsPosition, &Inventory), With<Collector>>,
+    mut item_query: Query<(&PhysicsPosition, &Item, &mut Velocity)>,
+    settings: Res<DroppedItemSettings>,
+    fixed_time: Res<FixedTime>,
+) {
+    let delta = fixed_time.period.as_secs_f32();
+    for (physics_position, item, mut velocity) in item_query.iter_mut() {
+        let position = physics_position.current;
+        let nearest = collector_query.iter()
+            .filter(|(_, inventory)| inventory.can_fit(item.0.item))
+            .map(|(collector_position, _)| collector_position.current - position)
+            .filter(|offset| offset.length() <= settings.magnet_radius)
+            .min_by(|a, b| a.length().total_cmp(&b.length()));
+        let Some(offset) = nearest else { continue; };
+
+        velocity.0 += offset.normalize_or_zero() * settings.magnet_acceleration * delta;
+    }
+}
+
+pub fn despawn_old(
+    mut commands: Commands,
+    mut item_query: Query<(Entity, &mut ItemAge)>,
+    settings: Res<DroppedItemSettings>,
+    time: Res<Time>,
+) {
+    for (entity, mut age) in item_query.iter_mut() {
+        age.0 += time.delta_seconds();
+        if age.0 > settings.lifetime {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
 pub fn animate(
     item_query: Query<&Children, With<Item>>,
     mut child_query: Query<(&mut Transform, &AnimationOffset)>,
@@ -91,7 +191,36 @@ pub fn animate(
     for children in item_query.iter() {
         for &child in children {
             let (mut transform, offset) = child_query.get_mut(child).unwrap();
-            transform.translation.y = (time.elapsed_seconds() * 4.0 + offset.0).sin() * 0.2 + 0.2;
+            transform.translation.y = (time.elapsed_seconds() * 4.0 + offset.0).sin() * 0.8 + 0.8;
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use crate::items::ItemId;
+
+    use super::*;
+
+    #[test]
+    fn nearby_items_merge_up_to_a_full_stack() {
+        let mut app = App::new();
+        app.init_resource::<DroppedItemSettings>();
+        app.add_systems(Update, merge);
+        for (x, count, age) in [(0.0, 40, 5.0), (4.0, 40, 2.0), (8.0, 40, 1.0), (100.0, 1, 0.0)] {
+            app.world.spawn((
+                Transform::from_xyz(x, 0.0, 0.0),
+                Item(ItemStack::new(ItemId::Stone, count)),
+                ItemAge(age),
+            ));
+        }
+        app.update();
+
+        // the third stack would overflow the first, the far one is out of range
+        let mut stacks: Vec<(u32, f32)> = app.world.query::<(&Item, &ItemAge)>()
+            .iter(&app.world)
+            .map(|(item, age)| (item.0.count, age.0))
+            .collect();
+        stacks.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(stacks, vec![(1, 0.0), (40, 1.0), (80, 2.0)]);
+    }
+}
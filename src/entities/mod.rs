@@ -1,30 +1,45 @@
 use bevy::prelude::*;
 
-use crate::states::GameState;
+use crate::{physics, states::GameState};
 
 pub mod boxy;
 pub mod item;
 pub mod arrow;
+pub mod health;
 
 pub struct EntitiesPlugin;
 
 impl Plugin for EntitiesPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<item::SpawnItem>();
+        app.init_resource::<item::DroppedItemSettings>();
         app.add_event::<arrow::SpawnArrow>();
 
         // app.add_systems(OnEnter(GameState::InGame), (
         //     boxy::spawn,
         // ).chain());
 
+        // right after bodies moved, so no step can slide or bounce a stuck arrow
+        app.add_systems(FixedUpdate, arrow::stick
+            .after(physics::contacts::find_contacts)
+            .run_if(in_state(GameState::InGame)));
+        app.add_systems(FixedUpdate, item::magnet
+            .before(physics::apply_gravity)
+            .run_if(in_state(GameState::InGame)));
+
         app.add_systems(Update,
             (
                 item::spawn,
+                item::merge,
                 item::check_collisions,
+                item::despawn_old,
                 item::animate,
                 arrow::spawn,
                 arrow::rotate,
+                arrow::hit,
                 arrow::check_collisions,
+                arrow::despawn_stuck,
+                health::despawn_dead,
             ).run_if(in_state(GameState::InGame)).chain(),
         );
     }
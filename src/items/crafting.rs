@@ -0,0 +1,114 @@
+use bevy::{math::ivec2, prelude::*, reflect::{TypePath, TypeUuid}};
+use bevy_asset_loader::prelude::AssetCollection;
+use bevy_egui::{egui, EguiContexts};
+use serde::Deserialize;
+
+use crate::{
+    entities::item::SpawnItem,
+    player::{inventory::{Inventory, InventoryOpen}, player::Player},
+    world::{block::Block, chunks::CHUNK_SIZE, position::ChunkPos, storage::WorldStorage},
+};
+
+use super::ItemStack;
+
+/// how close a crafting station has to be to the player, in blocks
+const STATION_RANGE: i32 = 4;
+
+#[derive(Deserialize)]
+pub struct Recipe {
+    pub inputs: Vec<ItemStack>,
+    pub outputs: Vec<ItemStack>,
+    /// block that has to be near the player to craft this
+    pub station: Option<Block>,
+}
+
+#[derive(Deserialize, TypeUuid, TypePath)]
+#[uuid = "5f0f7a3e-8c1b-4a57-9d0e-2b6f4c9a1e73"]
+pub struct RecipeBook {
+    pub recipes: Vec<Recipe>,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct RecipeAssets {
+    #[asset(path = "crafting.recipes.ron")]
+    pub recipes: Handle<RecipeBook>,
+}
+
+impl Recipe {
+    pub fn can_craft(&self, inventory: &Inventory, stations: &[Block]) -> bool {
+        let has_station = self.station.map_or(true, |station| stations.contains(&station));
+        has_station && self.inputs.iter().all(|input| inventory.count(input.item) >= input.count)
+    }
+}
+
+/// crafting station blocks around a position
+fn nearby_stations(world_storage: &WorldStorage, block_pos: IVec2) -> Vec<Block> {
+    let mut stations = Vec::new();
+    for y in -STATION_RANGE..=STATION_RANGE {
+        for x in -STATION_RANGE..=STATION_RANGE {
+            let pos = block_pos + ivec2(x, y);
+            let Some(chunk_data) = world_storage.get_chunk_data(ChunkPos::from_block_pos(pos)) else { continue; };
+            let rel_pos = IVec2::new(pos.x.rem_euclid(CHUNK_SIZE), pos.y.rem_euclid(CHUNK_SIZE));
+            let Some(block) = chunk_data.get_block(rel_pos) else { continue; };
+            if block.is_station() && !stations.contains(&block) {
+                stations.push(block);
+            }
+        }
+    }
+    stations
+}
+
+/// lists the recipes craftable from the inventory next to the inventory window
+pub fn crafting_ui(
+    mut contexts: EguiContexts,
+    inventory_open: Res<InventoryOpen>,
+    recipe_assets: Res<RecipeAssets>,
+    recipe_books: Res<Assets<RecipeBook>>,
+    world_storage: Res<WorldStorage>,
+    mut player_query: Query<(&Transform, &mut Inventory), With<Player>>,
+    mut item_event: EventWriter<SpawnItem>,
+) {
+    if !inventory_open.0 { return; };
+    let Some(recipe_book) = recipe_books.get(&recipe_assets.recipes) else { return; };
+    let (player_transform, mut inventory) = player_query.single_mut();
+    let player_pos = (player_transform.translation.truncate() / 8.0).round().as_ivec2();
+    let stations = nearby_stations(&world_storage, player_pos);
+
+    let mut crafted = None;
+    egui::Window::new("crafting").show(contexts.ctx_mut(), |ui| {
+        let mut any = false;
+        for (i, recipe) in recipe_book.recipes.iter().enumerate() {
+            if !recipe.can_craft(&inventory, &stations) { continue; };
+            any = true;
+
+            let inputs: Vec<String> = recipe.inputs.iter()
+                .map(|stack| format!("{} x{}", stack.item.name(), stack.count))
+                .collect();
+            let outputs: Vec<String> = recipe.outputs.iter()
+                .map(|stack| format!("{} x{}", stack.item.name(), stack.count))
+                .collect();
+            if ui.button(format!("{} <- {}", outputs.join(", "), inputs.join(", "))).clicked() {
+                crafted = Some(i);
+            }
+        }
+        if !any {
+            ui.label("nothing to craft");
+        }
+    });
+
+    let Some(i) = crafted else { return; };
+    let recipe = &recipe_book.recipes[i];
+    for input in recipe.inputs.iter() {
+        inventory.remove(input.item, input.count);
+    }
+    for output in recipe.outputs.iter() {
+        // drop what does not fit
+        let leftover = inventory.add(*output);
+        if leftover > 0 {
+            item_event.send(SpawnItem {
+                position: player_transform.translation.truncate(),
+                stack: ItemStack { count: leftover, ..*output },
+            });
+        }
+    }
+}
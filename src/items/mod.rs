@@ -0,0 +1,203 @@
+use bevy::prelude::*;
+use bevy_asset_loader::prelude::LoadingStateAppExt;
+use bevy_common_assets::ron::RonAssetPlugin;
+use serde::Deserialize;
+
+use crate::{states::GameState, world::block::Block};
+
+use self::{crafting::{RecipeAssets, RecipeBook}, tool::{Tool, ToolKind}};
+
+pub mod crafting;
+pub mod tool;
+
+pub struct ItemsPlugin;
+
+impl Plugin for ItemsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(RonAssetPlugin::<RecipeBook>::new(&["recipes.ron"]));
+        app.add_collection_to_loading_state::<_, RecipeAssets>(GameState::AssetLoading);
+
+        app.add_systems(Update, crafting::crafting_ui.run_if(in_state(GameState::InGame)));
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ItemKind {
+    Block,
+    Tool,
+    Material,
+    Consumable,
+}
+
+/// every item that can exist in an inventory, a chest or on the ground
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash, Deserialize)]
+#[repr(u32)]
+pub enum ItemId {
+    Dirt,
+    Stone,
+    Chest,
+    Pebble,
+    StonePickaxe,
+    Mushroom,
+    Workbench,
+    StoneAxe,
+    StoneHammer,
+    StoneSword,
+    Bow,
+    PressurePlate,
+    Platform,
+    StoneSlab,
+    StoneSlope,
+    Ice,
+}
+
+impl ItemId {
+    pub fn from(item: u32) -> Option<Self> {
+        match item {
+            0 => Some(Self::Dirt),
+            1 => Some(Self::Stone),
+            2 => Some(Self::Chest),
+            3 => Some(Self::Pebble),
+            4 => Some(Self::StonePickaxe),
+            5 => Some(Self::Mushroom),
+            6 => Some(Self::Workbench),
+            7 => Some(Self::StoneAxe),
+            8 => Some(Self::StoneHammer),
+            9 => Some(Self::StoneSword),
+            10 => Some(Self::Bow),
+            11 => Some(Self::PressurePlate),
+            12 => Some(Self::Platform),
+            13 => Some(Self::StoneSlab),
+            14 => Some(Self::StoneSlope),
+            15 => Some(Self::Ice),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            ItemId::Dirt => "dirt",
+            ItemId::Stone => "stone",
+            ItemId::Chest => "chest",
+            ItemId::Pebble => "pebble",
+            ItemId::StonePickaxe => "stone pickaxe",
+            ItemId::Mushroom => "mushroom",
+            ItemId::Workbench => "workbench",
+            ItemId::StoneAxe => "stone axe",
+            ItemId::StoneHammer => "stone hammer",
+            ItemId::StoneSword => "stone sword",
+            ItemId::Bow => "bow",
+            ItemId::PressurePlate => "pressure plate",
+            ItemId::Platform => "platform",
+            ItemId::StoneSlab => "stone slab",
+            ItemId::StoneSlope => "stone slope",
+            ItemId::Ice => "ice",
+        }
+    }
+
+    pub fn kind(&self) -> ItemKind {
+        match self {
+            ItemId::Dirt => ItemKind::Block,
+            ItemId::Stone => ItemKind::Block,
+            ItemId::Chest => ItemKind::Block,
+            ItemId::Pebble => ItemKind::Material,
+            ItemId::StonePickaxe => ItemKind::Tool,
+            ItemId::Mushroom => ItemKind::Consumable,
+            ItemId::Workbench => ItemKind::Block,
+            ItemId::StoneAxe => ItemKind::Tool,
+            ItemId::StoneHammer => ItemKind::Tool,
+            ItemId::StoneSword => ItemKind::Tool,
+            ItemId::Bow => ItemKind::Tool,
+            ItemId::PressurePlate => ItemKind::Block,
+            ItemId::Platform => ItemKind::Block,
+            ItemId::StoneSlab => ItemKind::Block,
+            ItemId::StoneSlope => ItemKind::Block,
+            ItemId::Ice => ItemKind::Block,
+        }
+    }
+
+    pub fn icon_path(&self) -> &str {
+        match self {
+            ItemId::Dirt => "tiles/dirt.png",
+            ItemId::Stone => "tiles/stone.png",
+            ItemId::Chest => "tiles/chest.png",
+            ItemId::Pebble => "items/pebble.png",
+            ItemId::StonePickaxe => "items/stone_pickaxe.png",
+            ItemId::Mushroom => "items/mushroom.png",
+            ItemId::Workbench => "tiles/workbench.png",
+            ItemId::StoneAxe => "items/stone_axe.png",
+            ItemId::StoneHammer => "items/stone_hammer.png",
+            ItemId::StoneSword => "items/stone_sword.png",
+            ItemId::Bow => "items/bow.png",
+            ItemId::PressurePlate => "tiles/pressure_plate.png",
+            ItemId::Platform => "tiles/platform.png",
+            ItemId::StoneSlab => "tiles/stone_slab.png",
+            ItemId::StoneSlope => "tiles/stone_slope.png",
+            ItemId::Ice => "tiles/ice.png",
+        }
+    }
+
+    pub fn max_stack(&self) -> u32 {
+        match self.kind() {
+            ItemKind::Tool => 1,
+            _ => 99,
+        }
+    }
+
+    /// block placed when using this item on an empty tile
+    pub fn places_block(&self) -> Option<Block> {
+        match self {
+            ItemId::Dirt => Some(Block::Dirt),
+            ItemId::Stone => Some(Block::Stone),
+            ItemId::Chest => Some(Block::Chest),
+            ItemId::Workbench => Some(Block::Workbench),
+            ItemId::PressurePlate => Some(Block::PressurePlate),
+            ItemId::Platform => Some(Block::Platform),
+            ItemId::StoneSlab => Some(Block::StoneSlab),
+            ItemId::StoneSlope => Some(Block::StoneSlope),
+            ItemId::Ice => Some(Block::Ice),
+            _ => None,
+        }
+    }
+
+    /// wall placed when using this item in wall mode
+    pub fn places_wall(&self) -> Option<Block> {
+        match self {
+            ItemId::Dirt => Some(Block::Dirt),
+            ItemId::Stone => Some(Block::Stone),
+            _ => None,
+        }
+    }
+
+    pub fn tool(&self) -> Option<Tool> {
+        let (kind, tier, speed, damage, durability) = match self {
+            ItemId::StonePickaxe => (ToolKind::Pickaxe, 1, 3.0, 2.0, 128),
+            ItemId::StoneAxe => (ToolKind::Axe, 1, 3.0, 3.0, 128),
+            ItemId::StoneHammer => (ToolKind::Hammer, 1, 2.0, 2.0, 128),
+            ItemId::StoneSword => (ToolKind::Sword, 1, 3.0, 5.0, 96),
+            ItemId::Bow => (ToolKind::Bow, 1, 1.5, 4.0, 64),
+            _ => return None,
+        };
+        Some(Tool { kind, tier, speed, damage, durability })
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Deserialize)]
+pub struct ItemStack {
+    pub item: ItemId,
+    pub count: u32,
+    /// durability used up, only meaningful for tools
+    #[serde(default)]
+    pub wear: u32,
+}
+
+impl ItemStack {
+    pub fn new(item: ItemId, count: u32) -> Self {
+        Self { item, count, wear: 0 }
+    }
+
+    /// uses left before the tool breaks
+    pub fn durability(&self) -> Option<u32> {
+        self.item.tool().map(|tool| tool.durability.saturating_sub(self.wear))
+    }
+}
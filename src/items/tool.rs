@@ -0,0 +1,28 @@
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ToolKind {
+    Pickaxe,
+    Axe,
+    /// breaks walls
+    Hammer,
+    Sword,
+    Bow,
+}
+
+impl ToolKind {
+    /// weapons attack with the use button instead of mining
+    pub fn is_weapon(&self) -> bool {
+        matches!(self, ToolKind::Sword | ToolKind::Bow)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tool {
+    pub kind: ToolKind,
+    /// blocks with a higher tier than the tool do not get its speed bonus
+    pub tier: u32,
+    /// mining power for mining tools, attacks per second for weapons
+    pub speed: f32,
+    pub damage: f32,
+    /// uses before the tool breaks
+    pub durability: u32,
+}
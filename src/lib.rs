@@ -8,6 +8,8 @@ mod world;
 mod debug;
 mod entities;
 mod physics;
+mod items;
+mod controls;
 
 pub fn app() -> App {
     let mut app = App::new();
@@ -24,6 +26,8 @@ pub fn app() -> App {
                     title: format!("astatine.rs"),
                     ..default()
                 }),
+                // closing is handled by world::save::save_on_exit so the world gets saved first
+                close_when_requested: false,
                 ..default()
             })
             .set(ImagePlugin::default_nearest()),
@@ -31,12 +35,14 @@ pub fn app() -> App {
         player::PlayerPlugin,
         entities::EntitiesPlugin,
         physics::PhysicsPlugin,
+        items::ItemsPlugin,
+        controls::ControlsPlugin,
     ));
 
     app.add_systems(Update, (   
-        bevy::window::close_on_esc,
         debug::debug_text,
         debug::screenshot,
+        debug::toggle_fly,
         // debug::chunk_borders,
         // debug::draw_colls,
     ).run_if(in_state(GameState::InGame)));
@@ -0,0 +1,257 @@
+use bevy::{math::{ivec2, vec2}, prelude::*};
+
+use crate::world::{block::BlockShape, chunks::BLOCK_SIZE, position::{tile, EPSILON}, storage::WorldStorage};
+
+/// bottom or left edge of a tile in world coordinates
+fn tile_start(tile: i32) -> f32 {
+    (tile * BLOCK_SIZE) as f32 - BLOCK_SIZE as f32 / 2.0
+}
+
+/// how a body treats shaped tiles while moving
+#[derive(Clone, Copy, Default)]
+pub struct MoveOptions {
+    /// falls through one way platforms instead of landing on them
+    pub drop_through: bool,
+    /// keeps a body that was on the ground there when it walks down slopes and slabs
+    pub snap_down: bool,
+}
+
+/// moves an axis aligned box by `motion` through the tile grid, one axis at a time and
+/// in steps of at most one tile so nothing tunnels. returns the new center and the
+/// normals of the surfaces it ran into, e.g. `y == 1` when landing on the ground
+pub fn move_and_collide(
+    world_storage: &WorldStorage,
+    center: Vec2,
+    half_size: Vec2,
+    motion: Vec2,
+    options: MoveOptions,
+) -> (Vec2, IVec2) {
+    let steps = (motion.abs().max_element() / BLOCK_SIZE as f32).ceil().max(1.0);
+    let mut step = motion / steps;
+    let mut center = center;
+    let mut normal = IVec2::ZERO;
+
+    for _ in 0..steps as i32 {
+        if step.y != 0.0 {
+            let (moved, hit) = sweep_y(world_storage, center, half_size, step.y, options.drop_through);
+            center.y += moved;
+            if hit {
+                normal.y = -step.y.signum() as i32;
+                step.y = 0.0;
+            }
+        }
+        if step.x != 0.0 {
+            let (moved, mut hit) = sweep_x(world_storage, center, half_size, step.x);
+            let moved_center = center + vec2(moved, 0.0);
+
+            // walks up slopes and slabs, unless there is no headroom
+            let climb = step_height(world_storage, moved_center, half_size);
+            if climb > 0.0 {
+                let (rise, blocked) = sweep_y(world_storage, moved_center, half_size, climb, false);
+                if blocked {
+                    hit = true;
+                } else {
+                    center = moved_center + vec2(0.0, rise);
+                    // rising bodies only brush past, they are not standing on it
+                    if step.y <= 0.0 {
+                        normal.y = 1;
+                    }
+                }
+            } else {
+                center = moved_center;
+            }
+
+            if hit {
+                normal.x = -step.x.signum() as i32;
+                step.x = 0.0;
+            }
+        }
+    }
+
+    // without this bodies would hop down ramps one fall at a time
+    if options.snap_down && motion.y <= 0.0 && normal.y == 0 {
+        let reach = motion.x.abs().max(BLOCK_SIZE as f32 / 2.0);
+        let (moved, hit) = sweep_y(world_storage, center, half_size, -reach, options.drop_through);
+        if hit {
+            center.y += moved;
+            normal.y = 1;
+        }
+    }
+    (center, normal)
+}
+
+/// how far the box gets horizontally before its leading edge reaches a blocking tile,
+/// tiles it already overlaps are ignored so it can never get stuck inside one.
+/// slopes and slabs only block boxes reaching them from below their bottom edge
+fn sweep_x(world_storage: &WorldStorage, center: Vec2, half_size: Vec2, delta: f32) -> (f32, bool) {
+    let bottom = center.y - half_size.y;
+    let y_min = tile(bottom + EPSILON);
+    let y_max = tile(center.y + half_size.y - EPSILON);
+
+    let blocks = |x: i32| (y_min..=y_max).any(|y| match world_storage.shape(ivec2(x, y)) {
+        BlockShape::Empty | BlockShape::Platform => false,
+        BlockShape::Full => true,
+        _ => bottom < tile_start(y) - EPSILON,
+    });
+
+    if delta > 0.0 {
+        let edge = center.x + half_size.x;
+        for x in (tile(edge - EPSILON) + 1)..=tile(edge + delta) {
+            if blocks(x) {
+                return ((tile_start(x) - edge).max(0.0), true);
+            }
+        }
+    } else {
+        let edge = center.x - half_size.x;
+        for x in (tile(edge + delta)..=(tile(edge + EPSILON) - 1)).rev() {
+            if blocks(x) {
+                return ((tile_start(x) + BLOCK_SIZE as f32 - edge).min(0.0), true);
+            }
+        }
+    }
+    (delta, false)
+}
+
+/// how far the box gets vertically. going up everything but platforms stops it at the
+/// tile's bottom edge, going down it lands on the highest surface it was above
+fn sweep_y(world_storage: &WorldStorage, center: Vec2, half_size: Vec2, delta: f32, drop_through: bool) -> (f32, bool) {
+    let left = center.x - half_size.x;
+    let right = center.x + half_size.x;
+    let x_min = tile(left + EPSILON);
+    let x_max = tile(right - EPSILON);
+
+    if delta > 0.0 {
+        let edge = center.y + half_size.y;
+        for y in (tile(edge - EPSILON) + 1)..=tile(edge + delta) {
+            let blocked = (x_min..=x_max).any(|x| {
+                !matches!(world_storage.shape(ivec2(x, y)), BlockShape::Empty | BlockShape::Platform)
+            });
+            if blocked {
+                return ((tile_start(y) - edge).max(0.0), true);
+            }
+        }
+    } else {
+        let edge = center.y - half_size.y;
+        // starts in the tile the edge is in, slabs and slopes have their surface inside it
+        for y in (tile(edge + delta)..=tile(edge + EPSILON)).rev() {
+            let surface = (x_min..=x_max)
+                .filter_map(|x| {
+                    let shape = world_storage.shape(ivec2(x, y));
+                    if shape == BlockShape::Empty { return None; };
+                    if shape == BlockShape::Platform && drop_through { return None; };
+
+                    let surface = tile_start(y) + shape.height(left - tile_start(x), right - tile_start(x));
+                    // surfaces the box is already below do not count
+                    (surface <= edge + EPSILON && surface > edge + delta).then_some(surface)
+                })
+                .reduce(f32::max);
+            if let Some(surface) = surface {
+                return ((surface - edge).min(0.0), true);
+            }
+        }
+    }
+    (delta, false)
+}
+
+/// how far the box has to rise to stand on the slopes and slabs its bottom edge is inside of
+fn step_height(world_storage: &WorldStorage, center: Vec2, half_size: Vec2) -> f32 {
+    let left = center.x - half_size.x;
+    let right = center.x + half_size.x;
+    let bottom = center.y - half_size.y;
+    let y = tile(bottom + EPSILON);
+
+    (tile(left + EPSILON)..=tile(right - EPSILON))
+        .filter_map(|x| {
+            let shape = world_storage.shape(ivec2(x, y));
+            if !shape.is_step() { return None; };
+            let surface = tile_start(y) + shape.height(left - tile_start(x), right - tile_start(x));
+            Some(surface - bottom)
+        })
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::block::Block;
+
+    use super::*;
+
+    const HALF_SIZE: Vec2 = Vec2::new(4.0, 8.0);
+
+    /// a slope rising to the right in tile (10, 1), whose left edge is at x = 76
+    fn world() -> WorldStorage {
+        let mut world_storage = WorldStorage::new();
+        world_storage.set_block(ivec2(10, 1), Block::StoneSlope);
+        world_storage
+    }
+
+    /// a stone floor whose top is at y = 4 and a ceiling whose bottom is at y = 36, between them
+    /// a one tile wall at x = 12 (right face at x = 100) and another at x = 26 (left face at x = 204)
+    fn room() -> WorldStorage {
+        let mut world_storage = WorldStorage::new();
+        for x in 0..40 {
+            world_storage.set_block(ivec2(x, 0), Block::Stone);
+            world_storage.set_block(ivec2(x, 5), Block::Stone);
+        }
+        for y in 1..5 {
+            world_storage.set_block(ivec2(12, y), Block::Stone);
+            world_storage.set_block(ivec2(26, y), Block::Stone);
+        }
+        world_storage
+    }
+
+    #[test]
+    fn fast_bodies_do_not_tunnel_through_thin_walls() {
+        // far more than a tile per call, the wall is a single tile thick
+        let (center, normal) = move_and_collide(&room(), vec2(110.0, 20.0), HALF_SIZE, vec2(-400.0, 0.0), MoveOptions::default());
+        assert_eq!(center, vec2(104.0, 20.0));
+        assert_eq!(normal, ivec2(1, 0));
+    }
+
+    #[test]
+    fn motion_keeps_going_on_the_free_axis_after_a_hit() {
+        // split into 8 steps of (-8, -1): the wall stops x on the sixth, y moves on every step
+        let (center, normal) = move_and_collide(&room(), vec2(150.0, 28.0), HALF_SIZE, vec2(-64.0, -8.0), MoveOptions::default());
+        assert_eq!(center, vec2(104.0, 20.0));
+        assert_eq!(normal, ivec2(1, 0));
+    }
+
+    #[test]
+    fn normals_point_away_from_what_was_hit() {
+        let cases = [
+            // floor, ceiling, wall to the left, wall to the right
+            (vec2(160.0, 20.0), vec2(0.0, -16.0), vec2(160.0, 12.0), ivec2(0, 1)),
+            (vec2(160.0, 20.0), vec2(0.0, 16.0), vec2(160.0, 28.0), ivec2(0, -1)),
+            (vec2(110.0, 20.0), vec2(-16.0, 0.0), vec2(104.0, 20.0), ivec2(1, 0)),
+            (vec2(190.0, 20.0), vec2(16.0, 0.0), vec2(200.0, 20.0), ivec2(-1, 0)),
+        ];
+        for (start, motion, end, expected_normal) in cases {
+            let (center, normal) = move_and_collide(&room(), start, HALF_SIZE, motion, MoveOptions::default());
+            assert_eq!((center, normal), (end, expected_normal), "moving {} from {}", motion, start);
+        }
+    }
+
+    #[test]
+    fn diagonal_motion_slides_along_the_floor_into_the_corner() {
+        // steps of (8, -8): reaches the floor on the first, then slides along it until the wall
+        let (center, normal) = move_and_collide(&room(), vec2(190.0, 20.0), HALF_SIZE, vec2(16.0, -16.0), MoveOptions::default());
+        assert_eq!(center, vec2(200.0, 12.0));
+        assert_eq!(normal, ivec2(-1, 1));
+    }
+
+    #[test]
+    fn walking_up_a_slope_stands_on_it() {
+        // standing at the height of the slope's foot, right next to it
+        let (center, normal) = move_and_collide(&world(), vec2(72.0, 12.0), HALF_SIZE, vec2(2.0, 0.0), MoveOptions::default());
+        assert_eq!(center, vec2(74.0, 14.0));
+        assert_eq!(normal, ivec2(0, 1));
+    }
+
+    #[test]
+    fn rising_past_a_slope_is_not_standing_on_it() {
+        let (center, normal) = move_and_collide(&world(), vec2(72.0, 12.0), HALF_SIZE, vec2(4.0, 1.0), MoveOptions::default());
+        // lifted onto the slope surface at x = 80, but still going up
+        assert_eq!(center, vec2(76.0, 16.0));
+        assert_eq!(normal, IVec2::ZERO);
+    }
+}
@@ -0,0 +1,270 @@
+use bevy::{prelude::*, utils::{HashMap, HashSet}};
+
+use super::{Collider, PhysicsPosition};
+
+/// side of a spatial hash cell, in pixels
+const CELL_SIZE: f32 = 32.0;
+
+/// which layers an entity is on and which layers it reports contacts with,
+/// two entities only touch if each one's filters include a layer of the other
+#[derive(Component, Clone, Copy)]
+pub struct CollisionLayers {
+    pub memberships: u32,
+    pub filters: u32,
+}
+
+impl CollisionLayers {
+    pub const PLAYER: u32 = 1 << 0;
+    pub const MOB: u32 = 1 << 1;
+    pub const ITEM: u32 = 1 << 2;
+    pub const PROJECTILE: u32 = 1 << 3;
+    pub const TRIGGER: u32 = 1 << 4;
+    pub const ALL: u32 = u32::MAX;
+
+    pub fn new(memberships: u32, filters: u32) -> Self {
+        Self { memberships, filters }
+    }
+
+    pub fn interacts(&self, other: &CollisionLayers) -> bool {
+        self.filters & other.memberships != 0 && other.filters & self.memberships != 0
+    }
+}
+
+impl Default for CollisionLayers {
+    fn default() -> Self {
+        Self::new(Self::ALL, Self::ALL)
+    }
+}
+
+/// colliders that only detect what passes through them, reported as `TriggerEvent`s
+#[derive(Component)]
+pub struct Trigger;
+
+/// two bodies started or stopped overlapping
+#[derive(Event, Clone, Copy, Debug)]
+pub enum CollisionEvent {
+    Enter(Entity, Entity),
+    Exit(Entity, Entity),
+}
+
+/// something started or stopped overlapping a trigger
+#[derive(Event, Clone, Copy, Debug)]
+pub enum TriggerEvent {
+    Enter { trigger: Entity, other: Entity },
+    Exit { trigger: Entity, other: Entity },
+}
+
+/// pairs of entities overlapping as of the last physics step, and the trigger of each pair if any
+#[derive(Resource, Default)]
+pub struct Contacts {
+    pairs: HashMap<(Entity, Entity), Option<Entity>>,
+}
+
+impl Contacts {
+    pub fn pairs(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+        self.pairs.keys().copied()
+    }
+
+    /// everything currently overlapping `entity`
+    pub fn touching(&self, entity: Entity) -> impl Iterator<Item = Entity> + '_ {
+        self.pairs().filter_map(move |(a, b)| {
+            if a == entity {
+                Some(b)
+            } else if b == entity {
+                Some(a)
+            } else {
+                None
+            }
+        })
+    }
+}
+
+fn ordered(a: Entity, b: Entity) -> (Entity, Entity) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+struct Body {
+    entity: Entity,
+    center: Vec2,
+    size: Vec2,
+    layers: CollisionLayers,
+    trigger: bool,
+}
+
+/// finds overlapping colliders by bucketing them into a spatial hash and only testing
+/// boxes that share a cell, then sends enter and exit events by diffing against the last step
+pub fn find_contacts(
+    q: Query<(Entity, &Collider, &Transform, Option<&PhysicsPosition>, Option<&CollisionLayers>, Option<&Trigger>)>,
+    mut contacts: ResMut<Contacts>,
+    mut collision_event: EventWriter<CollisionEvent>,
+    mut trigger_event: EventWriter<TriggerEvent>,
+) {
+    let bodies: Vec<Body> = q.iter()
+        .map(|(entity, collider, transform, position, layers, trigger)| {
+            // bodies are between steps here, static colliders like triggers only have a transform
+            let position = position.map_or(transform.translation.truncate(), |position| position.current);
+            Body {
+                entity,
+                center: collider.center(position),
+                size: collider.size,
+                layers: layers.copied().unwrap_or_default(),
+                trigger: trigger.is_some(),
+            }
+        })
+        .collect();
+
+    let mut grid: HashMap<IVec2, Vec<usize>> = HashMap::new();
+    for (i, body) in bodies.iter().enumerate() {
+        let min = ((body.center - body.size / 2.0) / CELL_SIZE).floor().as_ivec2();
+        let max = ((body.center + body.size / 2.0) / CELL_SIZE).floor().as_ivec2();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                grid.entry(IVec2::new(x, y)).or_default().push(i);
+            }
+        }
+    }
+
+    let mut pairs: HashMap<(Entity, Entity), Option<Entity>> = HashMap::new();
+    let mut tested: HashSet<(usize, usize)> = HashSet::new();
+    for cell in grid.values() {
+        for (n, &i) in cell.iter().enumerate() {
+            for &j in cell.iter().skip(n + 1) {
+                // big boxes share several cells with the same neighbours
+                if !tested.insert((i.min(j), i.max(j))) { continue; };
+
+                let (a, b) = (&bodies[i], &bodies[j]);
+                if a.trigger && b.trigger { continue; };
+                if !a.layers.interacts(&b.layers) { continue; };
+
+                let distance = (a.center - b.center).abs();
+                let reach = (a.size + b.size) / 2.0;
+                if distance.x >= reach.x || distance.y >= reach.y { continue; };
+
+                let trigger = match (a.trigger, b.trigger) {
+                    (true, _) => Some(a.entity),
+                    (_, true) => Some(b.entity),
+                    _ => None,
+                };
+                pairs.insert(ordered(a.entity, b.entity), trigger);
+            }
+        }
+    }
+
+    for (&(a, b), &trigger) in pairs.iter() {
+        if contacts.pairs.contains_key(&(a, b)) { continue; };
+        match trigger {
+            Some(trigger) => trigger_event.send(TriggerEvent::Enter { trigger, other: other(trigger, a, b) }),
+            None => collision_event.send(CollisionEvent::Enter(a, b)),
+        }
+    }
+    // also covers pairs where one side was despawned
+    for (&(a, b), &trigger) in contacts.pairs.iter() {
+        if pairs.contains_key(&(a, b)) { continue; };
+        match trigger {
+            Some(trigger) => trigger_event.send(TriggerEvent::Exit { trigger, other: other(trigger, a, b) }),
+            None => collision_event.send(CollisionEvent::Exit(a, b)),
+        }
+    }
+
+    contacts.pairs = pairs;
+}
+
+fn other(entity: Entity, a: Entity, b: Entity) -> Entity {
+    if entity == a { b } else { a }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.init_resource::<Contacts>();
+        app.add_event::<CollisionEvent>();
+        app.add_event::<TriggerEvent>();
+        app.add_systems(Update, find_contacts);
+        app
+    }
+
+    fn spawn(app: &mut App, position: Vec2, size: Vec2, layers: CollisionLayers) -> Entity {
+        app.world.spawn((Collider::new(size), Transform::from_translation(position.extend(0.0)), layers)).id()
+    }
+
+    fn collision_events(app: &mut App) -> Vec<CollisionEvent> {
+        app.world.resource_mut::<Events<CollisionEvent>>().drain().collect()
+    }
+
+    #[test]
+    fn layers_that_do_not_interact_never_touch() {
+        let mut app = app();
+        let player = CollisionLayers::new(CollisionLayers::PLAYER, CollisionLayers::ITEM);
+        let mob = CollisionLayers::new(CollisionLayers::MOB, CollisionLayers::ALL);
+        spawn(&mut app, Vec2::ZERO, Vec2::splat(8.0), player);
+        spawn(&mut app, Vec2::ZERO, Vec2::splat(8.0), mob);
+        app.update();
+
+        assert!(collision_events(&mut app).is_empty());
+        assert_eq!(app.world.resource::<Contacts>().pairs().count(), 0);
+    }
+
+    #[test]
+    fn enter_fires_once_and_exit_on_separation() {
+        let mut app = app();
+        let a = spawn(&mut app, Vec2::ZERO, Vec2::splat(8.0), CollisionLayers::default());
+        let b = spawn(&mut app, Vec2::new(4.0, 0.0), Vec2::splat(8.0), CollisionLayers::default());
+        let pair = ordered(a, b);
+
+        app.update();
+        let events = collision_events(&mut app);
+        assert!(matches!(events[..], [CollisionEvent::Enter(x, y)] if (x, y) == pair), "{:?}", events);
+
+        app.update();
+        assert!(collision_events(&mut app).is_empty());
+
+        // touching edges do not overlap
+        app.world.get_mut::<Transform>(b).unwrap().translation.x = 8.0;
+        app.update();
+        let events = collision_events(&mut app);
+        assert!(matches!(events[..], [CollisionEvent::Exit(x, y)] if (x, y) == pair), "{:?}", events);
+    }
+
+    #[test]
+    fn bodies_sharing_several_cells_touch_once() {
+        let mut app = app();
+        // both span a 4x4 block of cells
+        let a = spawn(&mut app, Vec2::ZERO, Vec2::splat(100.0), CollisionLayers::default());
+        spawn(&mut app, Vec2::new(10.0, 10.0), Vec2::splat(100.0), CollisionLayers::default());
+        app.update();
+
+        assert_eq!(collision_events(&mut app).len(), 1);
+        assert_eq!(app.world.resource::<Contacts>().touching(a).count(), 1);
+    }
+
+    #[test]
+    fn despawned_bodies_exit() {
+        let mut app = app();
+        let a = spawn(&mut app, Vec2::ZERO, Vec2::splat(8.0), CollisionLayers::default());
+        let b = spawn(&mut app, Vec2::ZERO, Vec2::splat(8.0), CollisionLayers::default());
+        app.update();
+        collision_events(&mut app);
+
+        app.world.despawn(b);
+        app.update();
+        let events = collision_events(&mut app);
+        assert!(matches!(events[..], [CollisionEvent::Exit(x, y)] if (x, y) == ordered(a, b)), "{:?}", events);
+        assert_eq!(app.world.resource::<Contacts>().touching(a).count(), 0);
+    }
+
+    #[test]
+    fn triggers_report_what_enters_them() {
+        let mut app = app();
+        let trigger = spawn(&mut app, Vec2::ZERO, Vec2::splat(8.0), CollisionLayers::default());
+        app.world.entity_mut(trigger).insert(Trigger);
+        let body = spawn(&mut app, Vec2::ZERO, Vec2::splat(8.0), CollisionLayers::default());
+        app.update();
+
+        assert!(collision_events(&mut app).is_empty());
+        let events: Vec<TriggerEvent> = app.world.resource_mut::<Events<TriggerEvent>>().drain().collect();
+        assert!(matches!(events[..], [TriggerEvent::Enter { trigger: t, other }] if t == trigger && other == body), "{:?}", events);
+    }
+}
@@ -1,18 +1,97 @@
-use bevy::{prelude::*, sprite::collide_aabb::{collide, Collision}, math::{vec3, vec2}};
+use bevy::{math::vec2, prelude::*};
 
-use crate::{states::GameState, world::chunks::Colls};
+use crate::{states::GameState, world::{position::tile_pos, storage::WorldStorage}};
 
+pub mod collision;
+pub mod contacts;
 // pub mod player;
 
 pub struct PhysicsPlugin;
 
+/// seconds per physics step
+const TIMESTEP: f32 = 1.0 / 60.0;
+pub(crate) const GRAVITY: f32 = 512.0;
+const MAX_SPEED: f32 = 512.0;
+const TERMINAL_VELOCITY: f32 = 320.0;
+/// slower impacts than this do not bounce, so resting bodies stay at rest
+const BOUNCE_THRESHOLD: f32 = 32.0;
+
 #[derive(Component)]
 pub struct Velocity(pub Vec2);
 
+/// box used for physics and overlap checks, independent of how the entity is drawn
+#[derive(Component, Clone, Copy)]
+pub struct Collider {
+    pub size: Vec2,
+    /// from the entity's position to the center of the box
+    pub offset: Vec2,
+}
+
+impl Collider {
+    pub fn new(size: Vec2) -> Self {
+        Self { size, offset: Vec2::ZERO }
+    }
+
+    /// center of the box for an entity at `position`
+    pub fn center(&self, position: Vec2) -> Vec2 {
+        position + self.offset
+    }
+
+    /// whether the box overlaps another box given by its center and size
+    pub fn overlaps_box(&self, position: Vec2, center: Vec2, size: Vec2) -> bool {
+        let distance = (self.center(position) - center).abs();
+        let reach = (self.size + size) / 2.0;
+        distance.x < reach.x && distance.y < reach.y
+    }
+
+    pub fn overlaps(&self, position: Vec2, other: &Collider, other_position: Vec2) -> bool {
+        self.overlaps_box(position, other.center(other_position), other.size)
+    }
+}
+
+/// how a body or block responds to contact. bodies without one use the default
+#[derive(Component, Clone, Copy)]
+pub struct PhysicsMaterial {
+    /// sliding speed lost per unit of speed lost pushing into a surface,
+    /// multiplied between the body and the block it touches
+    pub friction: f32,
+    /// share of the impact speed kept when bouncing off, the bouncier side wins
+    pub restitution: f32,
+    /// share of velocity lost per second, a body's own drag is added to that of the
+    /// block it is inside of, e.g. a liquid
+    pub drag: f32,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        Self {
+            friction: 1.0,
+            restitution: 0.0,
+            drag: 0.0,
+        }
+    }
+}
+
+/// where a body is in the simulation, its `Transform` is interpolated between the last two steps
+#[derive(Component)]
+pub struct PhysicsPosition {
+    pub current: Vec2,
+    pub previous: Vec2,
+}
+
+/// bodies that ignore gravity and tile collisions, like the player in fly mode
 #[derive(Component)]
+pub struct NoClip;
+
+#[derive(Component, Default)]
 pub struct Rigidbody {
     pub grounded: bool,
-    pub friction: bool
+    /// friction of the block last stood on, only meaningful while grounded
+    pub ground_friction: f32,
+    /// normals of the tiles touched during the last move, `y == 1` when standing on something
+    pub contact: IVec2,
+    /// falls through one way platforms, e.g. while the player holds down
+    pub drop_through: bool,
 }
 
 impl Plugin for PhysicsPlugin {
@@ -22,109 +101,123 @@ impl Plugin for PhysicsPlugin {
     //         player::spawn_player,
     //     ).chain());
 
-        app.add_systems(Update, (
+        app.insert_resource(FixedTime::new_from_secs(TIMESTEP));
+        app.init_resource::<contacts::Contacts>();
+        app.add_event::<contacts::CollisionEvent>();
+        app.add_event::<contacts::TriggerEvent>();
+
+        app.add_systems(FixedUpdate, (
+            store_previous_position,
             apply_gravity,
-            check_collision,
             apply_velocity,
+            contacts::find_contacts,
+        ).run_if(in_state(GameState::InGame)).chain());
+
+        app.add_systems(Update, (
+            insert_physics_position,
+            interpolate_transforms,
         ).run_if(in_state(GameState::InGame)).chain());
     }
 }
 
+/// new bodies start simulating from wherever they were spawned
+fn insert_physics_position(
+    mut commands: Commands,
+    q: Query<(Entity, &Transform), (With<Rigidbody>, Without<PhysicsPosition>)>,
+) {
+    for (entity, transform) in q.iter() {
+        let position = transform.translation.truncate();
+        commands.entity(entity).insert(PhysicsPosition { current: position, previous: position });
+    }
+}
+
+fn store_previous_position(mut q: Query<&mut PhysicsPosition>) {
+    for mut position in q.iter_mut() {
+        position.previous = position.current;
+    }
+}
+
+/// smooths out rendering between physics steps, running at any frame rate
+pub fn interpolate_transforms(
+    mut q: Query<(&mut Transform, &PhysicsPosition)>,
+    fixed_time: Res<FixedTime>,
+) {
+    let alpha = (fixed_time.accumulated().as_secs_f32() / fixed_time.period.as_secs_f32()).min(1.0);
+    for (mut transform, position) in q.iter_mut() {
+        let translation = position.previous.lerp(position.current, alpha);
+        transform.translation = translation.extend(transform.translation.z);
+    }
+}
+
 pub fn apply_gravity(
-    mut q: Query<&mut Velocity, With<Rigidbody>>,
-    time: Res<Time>
+    mut q: Query<&mut Velocity, (With<Rigidbody>, Without<NoClip>)>,
+    fixed_time: Res<FixedTime>,
 ) {
-    let delta = time.delta_seconds();
+    let delta = fixed_time.period.as_secs_f32();
     for mut velocity in q.iter_mut() {
-        velocity.0.y += -512.0 * delta;
-        velocity.0.x = velocity.0.x.clamp(-512.0, 512.0);
-        velocity.0.y = velocity.0.y.clamp(-512.0, 512.0);
+        velocity.0.y += -GRAVITY * delta;
+        velocity.0.x = velocity.0.x.clamp(-MAX_SPEED, MAX_SPEED);
+        velocity.0.y = velocity.0.y.clamp(-TERMINAL_VELOCITY, MAX_SPEED);
     }
 }
 
+/// moves bodies by their velocity, sweeping them through the tile grid unless they are `NoClip`,
+/// then bounces and slows them against whatever they hit based on both materials
 fn apply_velocity(
-    mut q: Query<(&mut Transform, &mut Velocity, &Rigidbody)>,
-    time: Res<Time>
+    mut q: Query<(&mut PhysicsPosition, &Collider, &mut Velocity, &mut Rigidbody, Option<&PhysicsMaterial>, Option<&NoClip>)>,
+    world_storage: Res<WorldStorage>,
+    fixed_time: Res<FixedTime>,
 ) {
-    let delta = time.delta_seconds();
-    for (mut transform, mut velocity, _rigidbody) in q.iter_mut() {
-        transform.translation += velocity.0.extend(0.0) * delta;
-        // if rigidbody.friction {
-        //     velocity.0.x *= 0.9;
-        // }
+    let delta = fixed_time.period.as_secs_f32();
+    for (mut position, collider, mut velocity, mut rigidbody, material, noclip) in q.iter_mut() {
+        if noclip.is_some() {
+            position.current += velocity.0 * delta;
+            continue;
+        }
+        let material = material.copied().unwrap_or_default();
+        let center = collider.center(position.current);
+        let half_size = collider.size / 2.0;
+
+        let medium = world_storage.material(tile_pos(center));
+        velocity.0 /= 1.0 + (material.drag + medium.drag) * delta;
+
+        let options = collision::MoveOptions {
+            drop_through: rigidbody.drop_through,
+            snap_down: rigidbody.grounded,
+        };
+        let (center, normal) = collision::move_and_collide(&world_storage, center, half_size, velocity.0 * delta, options);
+        position.current = center - collider.offset;
+
+        if normal.x != 0 {
+            let surface = world_storage.material(tile_pos(center - vec2(normal.x as f32 * (half_size.x + 1.0), 0.0)));
+            let impact = velocity.0.x.abs();
+            let Vec2 { x, y } = &mut velocity.0;
+            bounce(x, y, impact, &material, &surface);
+        }
+        if normal.y != 0 {
+            let surface = world_storage.material(tile_pos(center - vec2(0.0, normal.y as f32 * (half_size.y + 1.0))));
+            let impact = velocity.0.y.abs();
+            let Vec2 { x, y } = &mut velocity.0;
+            bounce(y, x, impact, &material, &surface);
+            if normal.y == 1 {
+                rigidbody.ground_friction = surface.friction;
+            }
+        }
         if velocity.0.x.abs() < 0.01 {
             velocity.0.x = 0.0;
         }
+
+        rigidbody.grounded = normal.y == 1;
+        rigidbody.contact = normal;
     }
 }
 
-pub fn check_collision(
-    // mut commands: Commands,
-    mut q: Query<(&mut Transform, &mut Velocity, Entity, &mut Rigidbody)>,
-    // mut player_queue: Query<&mut Player>,
-    colls: Res<Colls>,
-    time: Res<Time>
-) {
-    let delta = time.delta_seconds();
-    for (mut transform, mut velocity, _entity, mut rigidbody) in q.iter_mut() {
-        let mut should_ground = false;
-
-        for (_, hashset) in colls.0.iter() {
-            for (coll_transform, coll_length) in hashset.iter() {
-                // vertical collision
-                let coll_pos = Vec3 {
-                    x: coll_transform.x as f32 * 8.0 + *coll_length as f32 * 4.0 - 4.0,
-                    y: coll_transform.y as f32 * 8.0,
-                    z: 0.0 
-                };
-                let vertical_translation = transform.translation + vec3(0.0, velocity.0.y * delta, 0.0);
-                let coll = collide(
-                    vertical_translation,
-                    transform.scale.truncate(),
-                    // (coll_transform.as_vec2().extend(0.0) * 8.0) + *coll_length as f32 * 4.0,
-                    coll_pos,
-                    vec2(8.0 * *coll_length as f32, 8.0),
-                );
-    
-                if let Some(c) = coll {
-                    velocity.0.y = 0.0;
-                    if rigidbody.friction {
-                        velocity.0.x = 0.0;
-                        // commands.entity(entity).remove::<Rigidbody>();
-                    }
-                    if c == Collision::Top {
-                        transform.translation.y = (coll_transform.y as f32 * 8.0) + (transform.scale.y / 2.0 + 4.0);
-                        should_ground = true;
-                    }
-                }
-    
-                // horizontal collision
-                let horizontal_translation = transform.translation + vec3(velocity.0.x * delta, 0.0, 0.0);
-                let coll = collide(
-                    horizontal_translation,
-                    transform.scale.truncate(),
-                    coll_pos,
-                    vec2(8.0 * *coll_length as f32, 8.0),
-                );
-    
-                if let Some(c) = coll {
-                    velocity.0.x = 0.0;
-                    if rigidbody.friction {
-                        velocity.0.y = 0.0;
-                        // commands.entity(entity).remove::<Rigidbody>();
-                    }
-                    if c == Collision::Right {
-                        transform.translation.x = (coll_transform.x as f32 * 8.0) + (*coll_length * 8) as f32;
-                    } else if c == Collision::Left {
-                        transform.translation.x = (coll_transform.x as f32 * 8.0) - 8.0;
-                    }
-                }
-            }
-        }
+/// reflects the velocity into a surface if the impact was hard enough, and takes away sliding
+/// speed in proportion to the impact, so resting bodies slow down by `friction * GRAVITY`
+fn bounce(normal_velocity: &mut f32, tangent_velocity: &mut f32, impact: f32, body: &PhysicsMaterial, surface: &PhysicsMaterial) {
+    let restitution = body.restitution.max(surface.restitution);
+    *normal_velocity = if impact > BOUNCE_THRESHOLD { -*normal_velocity * restitution } else { 0.0 };
 
-        rigidbody.grounded = should_ground;
-        // if player_queue.contains(entity) {
-            // player_queue.get_mut(entity).unwrap().grounded = should_ground;
-        // }
-    }
+    let max_change = body.friction * surface.friction * impact;
+    *tangent_velocity -= tangent_velocity.clamp(-max_change, max_change);
 }
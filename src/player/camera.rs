@@ -1,6 +1,19 @@
 use std::ops::{Add, Div};
 
-use bevy::{prelude::*, core_pipeline::clear_color::ClearColorConfig};
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
+
+use crate::{
+    controls::{gamepad::{GamepadInput, InputDevice}, Action},
+    physics::Velocity,
+    world::{
+        chunks::{ChunkLoadRadius, ReloadChunks, BLOCK_SIZE, CHUNK_SIZE},
+        storage::WORLD_BLOCK_SIZE,
+    },
+};
 
 use super::player::Player;
 
@@ -10,57 +23,284 @@ pub struct PlayerCamera;
 #[derive(Resource)]
 pub struct CursorPosition(pub IVec2);
 
+#[derive(Clone, Copy, Debug)]
+pub enum FollowMode {
+    /// moves a fraction of the remaining distance, `speed` times per second
+    Lerp { speed: f32 },
+    /// critically damped spring, reaches the target in roughly `smooth_time` seconds
+    Spring { smooth_time: f32 },
+}
+
+#[derive(Resource)]
+pub struct CameraSettings {
+    pub mode: FollowMode,
+    /// half size of the box the player can move in without the camera following, in pixels
+    pub dead_zone: Vec2,
+    /// seconds of horizontal player velocity the camera looks ahead
+    pub look_ahead: f32,
+    pub max_look_ahead: f32,
+    /// round the camera position to whole screen pixels
+    pub pixel_snap: bool,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        Self {
+            mode: FollowMode::Spring { smooth_time: 0.15 },
+            dead_zone: Vec2::new(8.0, 16.0),
+            look_ahead: 0.25,
+            max_look_ahead: 32.0,
+            pixel_snap: true,
+        }
+    }
+}
+
+/// unsnapped camera state, `position` is `None` until the camera first jumps to the player
+#[derive(Component, Default)]
+pub struct CameraFollow {
+    position: Option<Vec2>,
+    velocity: Vec2,
+    focus: Vec2,
+    /// added on top of the focus, left behind by zooming towards the cursor
+    offset: Vec2,
+}
+
+impl CameraFollow {
+    /// moves the camera right away instead of easing there
+    fn shift(&mut self, by: Vec2) {
+        self.offset += by;
+        if let Some(position) = self.position.as_mut() {
+            *position += by;
+        }
+    }
+}
+
+/// pixels per world unit at each zoom level, whole numbers keep the tiles crisp
+const ZOOM_LEVELS: [f32; 6] = [1.0, 2.0, 3.0, 4.0, 6.0, 8.0];
+const DEFAULT_ZOOM_LEVEL: usize = 1;
+/// how quickly the projection eases to the selected level
+const ZOOM_SPEED: f32 = 12.0;
+/// extra distance past the edge of the view to keep chunks loaded, in pixels
+const CHUNK_LOAD_MARGIN: f32 = 64.0;
+
+#[derive(Resource)]
+pub struct Zoom {
+    pub level: usize,
+    /// wheel movement that did not add up to a whole step yet
+    scroll: f32,
+    /// world position that stays under the cursor while zooming in with the wheel
+    anchor: Option<Vec2>,
+}
+
+impl Default for Zoom {
+    fn default() -> Self {
+        Self {
+            level: DEFAULT_ZOOM_LEVEL,
+            scroll: 0.0,
+            anchor: None,
+        }
+    }
+}
+
 pub fn spawn_camera(mut commands: Commands) {
     let mut camera_bundle = Camera2dBundle::default();
-    camera_bundle.projection.scale = 0.5;
+    camera_bundle.projection.scale = 1.0 / ZOOM_LEVELS[DEFAULT_ZOOM_LEVEL];
     camera_bundle.camera_2d.clear_color = ClearColorConfig::Custom(Color::rgb(71./255., 209./255., 1.));
 
     commands.spawn((
         camera_bundle,
-        PlayerCamera
+        PlayerCamera,
+        CameraFollow::default(),
     ));
 }
 
 pub fn follow_player(
-    mut camera_query: Query<&mut Transform, With<PlayerCamera>>,
-    player_query: Query<&Transform, (With<Player>, Without<PlayerCamera>)>
+    settings: Res<CameraSettings>,
+    mut camera_query: Query<(&mut Transform, &mut CameraFollow, &OrthographicProjection), With<PlayerCamera>>,
+    player_query: Query<(&Transform, &Velocity), (With<Player>, Without<PlayerCamera>)>,
+    time: Res<Time>,
 ) {
-    let mut camera_transform = camera_query.single_mut();
-    let player_transform = player_query.single();
+    let (mut camera_transform, mut follow, projection) = camera_query.single_mut();
+    let (player_transform, player_velocity) = player_query.single();
+    let player_pos = player_transform.translation.truncate();
+    let delta = time.delta_seconds();
+
+    let Some(position) = follow.position else {
+        follow.position = Some(player_pos);
+        follow.focus = player_pos;
+        camera_transform.translation = player_pos.extend(camera_transform.translation.z);
+        return;
+    };
+
+    // the focus only moves once the player leaves the dead zone around it
+    let offset = player_pos - follow.focus;
+    follow.focus += offset - offset.clamp(-settings.dead_zone, settings.dead_zone);
+
+    let look_ahead = (player_velocity.0.x * settings.look_ahead).clamp(-settings.max_look_ahead, settings.max_look_ahead);
+    let half_view = projection.area.half_size();
+    // keep the player on screen however far the camera was zoomed off to the side
+    follow.offset = follow.offset.clamp(-half_view / 2.0, half_view / 2.0);
+    let target = clamp_to_world(follow.focus + follow.offset + Vec2::new(look_ahead, 0.0), half_view);
 
-    camera_transform.translation = player_transform.translation;
+    let position = match settings.mode {
+        FollowMode::Lerp { speed } => position.lerp(target, 1.0 - (-speed * delta).exp()),
+        FollowMode::Spring { smooth_time } => smooth_damp(position, target, &mut follow.velocity, smooth_time, delta),
+    };
+    let position = clamp_to_world(position, half_view);
+    follow.position = Some(position);
+
+    let translation = if settings.pixel_snap {
+        (position / projection.scale).round() * projection.scale
+    } else {
+        position
+    };
+    camera_transform.translation = translation.extend(camera_transform.translation.z);
+}
+
+/// keeps the view inside the world, centering it on axes where the world is smaller than the view
+fn clamp_to_world(position: Vec2, half_view: Vec2) -> Vec2 {
+    let min = Vec2::splat(-4.0) + half_view;
+    let max = WORLD_BLOCK_SIZE.as_vec2() * 8.0 - 4.0 - half_view;
+    let center = (WORLD_BLOCK_SIZE.as_vec2() * 8.0 - 8.0) / 2.0;
+    Vec2::new(
+        if min.x > max.x { center.x } else { position.x.clamp(min.x, max.x) },
+        if min.y > max.y { center.y } else { position.y.clamp(min.y, max.y) },
+    )
 }
 
+/// critically damped spring towards `target`, frame rate independent
+fn smooth_damp(current: Vec2, target: Vec2, velocity: &mut Vec2, smooth_time: f32, delta: f32) -> Vec2 {
+    let omega = 2.0 / smooth_time.max(0.0001);
+    let x = omega * delta;
+    let decay = 1.0 / (1.0 + x + 0.48 * x * x + 0.235 * x * x * x);
+    let change = current - target;
+    let temp = (*velocity + omega * change) * delta;
+    *velocity = (*velocity - omega * temp) * decay;
+    target + (change + temp) * decay
+}
+
+/// follows the window cursor, or the virtual aim cursor around the player when using a gamepad
 pub fn update_cursor_position(
     windows: Query<&Window>,
     camera_query: Query<(&Camera, &GlobalTransform), With<PlayerCamera>>,
+    player_query: Query<&Transform, With<Player>>,
+    input_device: Res<InputDevice>,
+    gamepad_input: Res<GamepadInput>,
     mut res_cursor_pos: ResMut<CursorPosition>
 ) {
-    let window = windows.single();
-    let Some(cursor_pos) = window.cursor_position() else { return; };
-    let (camera, camera_global_transform) = camera_query.single();
-
-    let Some(world_cursor_pos) = camera.viewport_to_world_2d(camera_global_transform, cursor_pos) else { return; };
+    let world_cursor_pos = match *input_device {
+        InputDevice::KeyboardMouse => {
+            let window = windows.single();
+            let Some(cursor_pos) = window.cursor_position() else { return; };
+            let (camera, camera_global_transform) = camera_query.single();
+            let Some(world_cursor_pos) = camera.viewport_to_world_2d(camera_global_transform, cursor_pos) else { return; };
+            world_cursor_pos
+        }
+        InputDevice::Gamepad => player_query.single().translation.truncate() + gamepad_input.aim,
+    };
     let tile_cursor_pos = world_cursor_pos.as_ivec2().add(4).div(8); // change this if something breaks
     res_cursor_pos.0 = tile_cursor_pos;
 }
 
+pub fn draw_gamepad_cursor(
+    mut gizmos: Gizmos,
+    input_device: Res<InputDevice>,
+    gamepad_input: Res<GamepadInput>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    if *input_device != InputDevice::Gamepad { return; };
+    let position = player_query.single().translation.truncate() + gamepad_input.aim;
+    gizmos.circle_2d(position, 2.0, Color::WHITE);
+}
+
 
+/// steps through the zoom levels with the zoom keys, or the wheel while holding the wheel zoom key.
+/// the projection eases to the new level, zooming in towards the cursor when using the wheel
 pub fn zoom(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut q: Query<&mut OrthographicProjection, With<PlayerCamera>>,
-    time: Res<Time>
+    actions: Res<Input<Action>>,
+    mut scroll_events: EventReader<MouseWheel>,
+    windows: Query<&Window>,
+    mut zoom: ResMut<Zoom>,
+    mut camera_query: Query<(&Camera, &GlobalTransform, &mut OrthographicProjection, &mut CameraFollow), With<PlayerCamera>>,
+    mut load_radius: ResMut<ChunkLoadRadius>,
+    mut reload_event: EventWriter<ReloadChunks>,
+    time: Res<Time>,
 ) {
-    let mut proj = q.single_mut();
-    let delta = time.delta_seconds();
+    let (camera, camera_global_transform, mut projection, mut follow) = camera_query.single_mut();
+    let window = windows.single();
+    let max_level = ZOOM_LEVELS.len() - 1;
+
+    if actions.just_pressed(Action::ZoomIn) && zoom.level < max_level {
+        zoom.level += 1;
+        zoom.anchor = None;
+    }
+    if actions.just_pressed(Action::ZoomOut) && zoom.level > 0 {
+        zoom.level -= 1;
+        zoom.anchor = None;
+    }
+    if actions.just_pressed(Action::ZoomReset) {
+        zoom.level = DEFAULT_ZOOM_LEVEL;
+        zoom.anchor = None;
+    }
+
+    for ev in scroll_events.iter() {
+        if !actions.pressed(Action::WheelZoom) { continue; };
+        zoom.scroll += match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            MouseScrollUnit::Pixel => ev.y / 16.0,
+        };
+    }
+    while zoom.scroll >= 1.0 {
+        zoom.scroll -= 1.0;
+        if zoom.level < max_level {
+            zoom.level += 1;
+            zoom.anchor = window.cursor_position()
+                .and_then(|cursor_pos| camera.viewport_to_world_2d(camera_global_transform, cursor_pos));
+        }
+    }
+    while zoom.scroll <= -1.0 {
+        zoom.scroll += 1.0;
+        if zoom.level > 0 {
+            zoom.level -= 1;
+            zoom.anchor = None;
+        }
+    }
 
-    if keyboard_input.pressed(KeyCode::Minus) {
-        proj.scale += 0.1 * delta;
+    let target_scale = 1.0 / ZOOM_LEVELS[zoom.level];
+    let old_scale = projection.scale;
+    let new_scale = if (target_scale - old_scale).abs() < 0.001 {
+        target_scale
+    } else {
+        old_scale + (target_scale - old_scale) * (1.0 - (-ZOOM_SPEED * time.delta_seconds()).exp())
+    };
+
+    if new_scale != old_scale {
+        projection.scale = new_scale;
+        let ratio = new_scale / old_scale;
+        match zoom.anchor {
+            // keep the anchor at the same spot on screen
+            Some(anchor) if ratio < 1.0 => {
+                let camera_pos = camera_global_transform.translation().truncate();
+                follow.shift((anchor - camera_pos) * (1.0 - ratio));
+            }
+            // zooming out drifts back towards the player
+            _ if ratio > 1.0 => {
+                let offset = follow.offset;
+                follow.shift(offset * (1.0 / ratio - 1.0));
+            }
+            _ => {}
+        }
     }
-    if keyboard_input.pressed(KeyCode::Equals) {
-        proj.scale -= 0.1 * delta;
+    if new_scale == target_scale {
+        zoom.anchor = None;
     }
-    if keyboard_input.pressed(KeyCode::Back) {
-        proj.scale = 0.5;
+
+    // load every chunk the view can reach at this zoom
+    let half_view = Vec2::new(window.width(), window.height()) * new_scale / 2.0;
+    let chunk_size = (CHUNK_SIZE * BLOCK_SIZE) as f32;
+    let radius = ((half_view + CHUNK_LOAD_MARGIN) / chunk_size).ceil().as_ivec2();
+    if load_radius.0 != radius {
+        load_radius.0 = radius;
+        reload_event.send(ReloadChunks);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,322 @@
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{controls::Action, items::{ItemId, ItemStack}};
+
+use super::player::Player;
+
+pub const INVENTORY_SLOTS: usize = 40;
+pub const HOTBAR_SLOTS: usize = 10;
+
+const HOTBAR_ACTIONS: [Action; HOTBAR_SLOTS] = [
+    Action::Hotbar1,
+    Action::Hotbar2,
+    Action::Hotbar3,
+    Action::Hotbar4,
+    Action::Hotbar5,
+    Action::Hotbar6,
+    Action::Hotbar7,
+    Action::Hotbar8,
+    Action::Hotbar9,
+    Action::Hotbar10,
+];
+
+/// the first `HOTBAR_SLOTS` slots make up the hotbar
+#[derive(Component)]
+pub struct Inventory {
+    pub slots: Vec<Option<ItemStack>>,
+    pub selected: usize,
+}
+
+#[derive(Resource, Default)]
+pub struct InventoryOpen(pub bool);
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![None; INVENTORY_SLOTS],
+            selected: 0,
+        }
+    }
+
+    /// merges into existing stacks first, then fills empty slots.
+    /// returns how many items did not fit
+    pub fn add(&mut self, stack: ItemStack) -> u32 {
+        add_to_slots(&mut self.slots, stack)
+    }
+
+    /// whether at least one of the item can be added
+    pub fn can_fit(&self, item: ItemId) -> bool {
+        self.slots.iter().any(|slot| match slot {
+            Some(stack) => stack.item == item && stack.count < item.max_stack(),
+            None => true,
+        })
+    }
+
+    pub fn count(&self, item: ItemId) -> u32 {
+        self.slots.iter().flatten()
+            .filter(|stack| stack.item == item)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    /// removes items from any stacks holding them, returns false without removing anything if there are not enough
+    pub fn remove(&mut self, item: ItemId, count: u32) -> bool {
+        if self.count(item) < count { return false; };
+
+        let mut count = count;
+        for slot in self.slots.iter_mut().rev() {
+            if count == 0 { break; };
+            let Some(stack) = slot else { continue; };
+            if stack.item != item { continue; };
+
+            let removed = count.min(stack.count);
+            stack.count -= removed;
+            count -= removed;
+            if stack.count == 0 {
+                *slot = None;
+            }
+        }
+        true
+    }
+
+    pub fn selected_stack(&self) -> Option<&ItemStack> {
+        self.slots[self.selected].as_ref()
+    }
+
+    /// removes items from the selected stack, clearing the slot once it is empty
+    pub fn remove_selected(&mut self, count: u32) {
+        let slot = &mut self.slots[self.selected];
+        let Some(stack) = slot else { return; };
+        stack.count = stack.count.saturating_sub(count);
+        if stack.count == 0 {
+            *slot = None;
+        }
+    }
+
+    /// wears down the selected tool by one use, breaking it once its durability runs out
+    pub fn wear_selected(&mut self) {
+        let slot = &mut self.slots[self.selected];
+        let Some(stack) = slot else { return; };
+        if stack.durability().is_none() { return; };
+        stack.wear += 1;
+        if stack.durability() == Some(0) {
+            *slot = None;
+        }
+    }
+
+    /// moves a stack onto another slot, merging identical items and swapping otherwise
+    pub fn move_stack(&mut self, from: usize, to: usize) {
+        if from == to { return; };
+
+        match (self.slots[from], self.slots[to]) {
+            // a full stack, or a tool, swaps with its twin instead of doing nothing
+            (Some(from_stack), Some(to_stack))
+                if from_stack.item == to_stack.item && to_stack.count < to_stack.item.max_stack() =>
+            {
+                let moved = from_stack.count.min(to_stack.item.max_stack().saturating_sub(to_stack.count));
+                self.slots[to] = Some(ItemStack { count: to_stack.count + moved, ..to_stack });
+                self.slots[from] = match from_stack.count - moved {
+                    0 => None,
+                    count => Some(ItemStack { count, ..from_stack }),
+                };
+            }
+            _ => self.slots.swap(from, to),
+        }
+    }
+}
+
+/// what `Inventory::add` does, for any container of slots
+pub fn add_to_slots(slots: &mut [Option<ItemStack>], stack: ItemStack) -> u32 {
+    let mut count = stack.count;
+
+    for slot in slots.iter_mut().flatten() {
+        if count == 0 { break; };
+        if slot.item != stack.item { continue; };
+        let moved = count.min(stack.item.max_stack().saturating_sub(slot.count));
+        slot.count += moved;
+        count -= moved;
+    }
+
+    for slot in slots.iter_mut() {
+        if count == 0 { break; };
+        if slot.is_some() { continue; };
+        let moved = count.min(stack.item.max_stack());
+        *slot = Some(ItemStack { count: moved, ..stack });
+        count -= moved;
+    }
+
+    count
+}
+
+pub fn select_hotbar(
+    actions: Res<Input<Action>>,
+    mut scroll_events: EventReader<MouseWheel>,
+    mut player_query: Query<&mut Inventory, With<Player>>,
+) {
+    let mut inventory = player_query.single_mut();
+
+    for (i, action) in HOTBAR_ACTIONS.iter().enumerate() {
+        if actions.just_pressed(*action) {
+            inventory.selected = i;
+        }
+    }
+
+    for ev in scroll_events.iter() {
+        // the wheel zooms instead, see `camera::zoom`
+        if actions.pressed(Action::WheelZoom) { continue; };
+        if ev.y > 0.0 {
+            inventory.selected = (inventory.selected + HOTBAR_SLOTS - 1) % HOTBAR_SLOTS;
+        } else if ev.y < 0.0 {
+            inventory.selected = (inventory.selected + 1) % HOTBAR_SLOTS;
+        }
+    }
+
+    if actions.just_pressed(Action::HotbarPrev) {
+        inventory.selected = (inventory.selected + HOTBAR_SLOTS - 1) % HOTBAR_SLOTS;
+    }
+    if actions.just_pressed(Action::HotbarNext) {
+        inventory.selected = (inventory.selected + 1) % HOTBAR_SLOTS;
+    }
+}
+
+pub fn toggle_inventory(
+    actions: Res<Input<Action>>,
+    mut inventory_open: ResMut<InventoryOpen>,
+) {
+    if actions.just_pressed(Action::Inventory) {
+        inventory_open.0 = !inventory_open.0;
+    }
+}
+
+pub fn hotbar_ui(
+    mut contexts: EguiContexts,
+    player_query: Query<&Inventory, With<Player>>,
+) {
+    let inventory = player_query.single();
+
+    egui::Area::new("hotbar")
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -8.0])
+        .show(contexts.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                for (i, slot) in inventory.slots.iter().take(HOTBAR_SLOTS).enumerate() {
+                    ui.add_sized([64.0, 32.0], egui::SelectableLabel::new(i == inventory.selected, slot_text(slot)));
+                }
+            });
+        });
+}
+
+pub fn inventory_ui(
+    mut contexts: EguiContexts,
+    mut inventory_open: ResMut<InventoryOpen>,
+    mut player_query: Query<&mut Inventory, With<Player>>,
+    mut dragged: Local<Option<usize>>,
+) {
+    if !inventory_open.0 {
+        *dragged = None;
+        return;
+    }
+    let mut inventory = player_query.single_mut();
+    let ctx = contexts.ctx_mut();
+
+    let mut drop_target = None;
+    let released = ctx.input(|i| i.pointer.any_released());
+
+    egui::Window::new("inventory").open(&mut inventory_open.0).show(ctx, |ui| {
+        egui::Grid::new("inventory_slots").show(ui, |ui| {
+            for i in 0..INVENTORY_SLOTS {
+                let response = ui
+                    .add_sized([64.0, 32.0], egui::SelectableLabel::new(i == inventory.selected, slot_text(&inventory.slots[i])))
+                    .interact(egui::Sense::drag());
+
+                if response.drag_started() && inventory.slots[i].is_some() {
+                    *dragged = Some(i);
+                }
+                if released && dragged.is_some() && ui.rect_contains_pointer(response.rect) {
+                    drop_target = Some(i);
+                }
+                if (i + 1) % HOTBAR_SLOTS == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+    });
+
+    if let Some(from) = *dragged {
+        egui::show_tooltip_at_pointer(ctx, egui::Id::new("dragged_stack"), |ui| {
+            ui.label(slot_text(&inventory.slots[from]));
+        });
+    }
+
+    if released {
+        if let (Some(from), Some(to)) = (*dragged, drop_target) {
+            inventory.move_stack(from, to);
+        }
+        *dragged = None;
+    }
+}
+
+pub fn slot_text(slot: &Option<ItemStack>) -> String {
+    match slot {
+        Some(stack) => match (stack.item.tool(), stack.durability()) {
+            (Some(tool), Some(durability)) => format!("{} {}/{}", stack.item.name(), durability, tool.durability),
+            _ => format!("{} x{}", stack.item.name(), stack.count),
+        },
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_merges_before_filling_empty_slots() {
+        let mut slots = vec![None, Some(ItemStack::new(ItemId::Dirt, 90)), None];
+        assert_eq!(add_to_slots(&mut slots, ItemStack::new(ItemId::Dirt, 20)), 0);
+        assert_eq!(slots, vec![Some(ItemStack::new(ItemId::Dirt, 11)), Some(ItemStack::new(ItemId::Dirt, 99)), None]);
+    }
+
+    #[test]
+    fn add_returns_what_did_not_fit() {
+        let mut slots = vec![Some(ItemStack::new(ItemId::Stone, 98)), None];
+        assert_eq!(add_to_slots(&mut slots, ItemStack::new(ItemId::Stone, 150)), 50);
+        assert_eq!(slots, vec![Some(ItemStack::new(ItemId::Stone, 99)), Some(ItemStack::new(ItemId::Stone, 99))]);
+    }
+
+    #[test]
+    fn tools_keep_their_wear_and_never_stack() {
+        let worn = ItemStack { item: ItemId::StonePickaxe, count: 1, wear: 5 };
+        let mut slots = vec![Some(ItemStack::new(ItemId::StonePickaxe, 1)), None, None];
+        assert_eq!(add_to_slots(&mut slots, worn), 0);
+        assert_eq!(slots[1], Some(worn));
+        assert_eq!(slots[2], None);
+    }
+
+    #[test]
+    fn moving_onto_a_stack_with_no_room_swaps() {
+        let worn = ItemStack { item: ItemId::StonePickaxe, count: 1, wear: 5 };
+        let mut inventory = Inventory::new();
+        inventory.slots[0] = Some(worn);
+        inventory.slots[1] = Some(ItemStack::new(ItemId::StonePickaxe, 1));
+        inventory.move_stack(0, 1);
+        assert_eq!(inventory.slots[0], Some(ItemStack::new(ItemId::StonePickaxe, 1)));
+        assert_eq!(inventory.slots[1], Some(worn));
+
+        inventory.slots[2] = Some(ItemStack::new(ItemId::Dirt, 5));
+        inventory.slots[3] = Some(ItemStack::new(ItemId::Dirt, 99));
+        inventory.move_stack(2, 3);
+        assert_eq!(inventory.slots[2], Some(ItemStack::new(ItemId::Dirt, 99)));
+        assert_eq!(inventory.slots[3], Some(ItemStack::new(ItemId::Dirt, 5)));
+    }
+
+    #[test]
+    fn moving_onto_a_stack_merges_what_fits() {
+        let mut inventory = Inventory::new();
+        inventory.slots[0] = Some(ItemStack::new(ItemId::Dirt, 20));
+        inventory.slots[1] = Some(ItemStack::new(ItemId::Dirt, 90));
+        inventory.move_stack(0, 1);
+        assert_eq!(inventory.slots[0], Some(ItemStack::new(ItemId::Dirt, 11)));
+        assert_eq!(inventory.slots[1], Some(ItemStack::new(ItemId::Dirt, 99)));
+    }
+}
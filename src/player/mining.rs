@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+
+use crate::{
+    controls::Action,
+    entities::item::SpawnItem,
+    items::{tool::ToolKind, ItemStack},
+    world::{
+        block::Block,
+        chunks::ReloadChunk,
+        position::ChunkPos,
+        storage::WorldStorage,
+    },
+};
+
+use super::{inventory::Inventory, player::Player, target::{BlockTarget, WallMode}};
+
+const CRACK_STAGES: usize = 4;
+
+/// how fast the player breaks blocks, multiplies against block hardness
+#[derive(Component)]
+pub struct MiningPower(pub f32);
+
+/// progress on the block currently being mined, from 0 to 1
+#[derive(Resource, Default)]
+pub struct MiningProgress {
+    pub target: Option<IVec2>,
+    /// whether the wall behind `target` is being mined instead of the block
+    pub wall: bool,
+    pub progress: f32,
+}
+
+#[derive(Component)]
+pub struct CrackOverlay;
+
+impl MiningProgress {
+    fn reset(&mut self) {
+        self.target = None;
+        self.progress = 0.0;
+    }
+}
+
+pub fn spawn_crack_overlay(mut commands: Commands) {
+    commands.spawn((
+        SpriteBundle {
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        CrackOverlay,
+    ));
+}
+
+pub fn mine(
+    mut contexts: EguiContexts,
+    block_target: Res<BlockTarget>,
+    wall_mode: Res<WallMode>,
+    actions: Res<Input<Action>>,
+    mut player_query: Query<(&MiningPower, &mut Inventory), With<Player>>,
+    mut mining_progress: ResMut<MiningProgress>,
+    mut world_storage: ResMut<WorldStorage>,
+    mut reload_event: EventWriter<ReloadChunk>,
+    mut item_event: EventWriter<SpawnItem>,
+    time: Res<Time>,
+) {
+    if !actions.pressed(Action::Mine) || contexts.ctx_mut().wants_pointer_input() {
+        mining_progress.reset();
+        return;
+    }
+
+    let (mining_power, mut inventory) = player_query.single_mut();
+    let tool = inventory.selected_stack().and_then(|stack| stack.item.tool());
+    // weapons attack instead, see `player::mouse_attack`
+    if tool.is_some_and(|tool| tool.kind.is_weapon()) {
+        mining_progress.reset();
+        return;
+    }
+
+    // walls can only be reached through empty tiles
+    let target = if wall_mode.0 { block_target.empty() } else { block_target.solid() };
+    let Some(target) = target else {
+        mining_progress.reset();
+        return;
+    };
+    if mining_progress.target != Some(target) || mining_progress.wall != wall_mode.0 {
+        mining_progress.target = Some(target);
+        mining_progress.wall = wall_mode.0;
+        mining_progress.progress = 0.0;
+    }
+
+    let block = if wall_mode.0 { world_storage.get_wall(target) } else { world_storage.get_block(target) };
+    let Some(block) = block else { return; };
+    if block == Block::Air {
+        mining_progress.progress = 0.0;
+        return;
+    };
+
+    // the right tool only helps if it is good enough for the block, walls always want a hammer
+    let preferred_tool = if wall_mode.0 { Some(ToolKind::Hammer) } else { block.tool() };
+    let tool = tool.filter(|tool| Some(tool.kind) == preferred_tool && tool.tier >= block.tier());
+    let speed = tool.map_or(1.0, |tool| tool.speed);
+    mining_progress.progress += time.delta_seconds() * mining_power.0 * speed / block.hardness();
+    if mining_progress.progress < 1.0 {
+        return;
+    }
+
+    if let Some(item) = block.drop_item() {
+        item_event.send(SpawnItem {
+            position: target.as_vec2() * 8.0,
+            stack: ItemStack::new(item, 1),
+        });
+    }
+    if tool.is_some() {
+        inventory.wear_selected();
+    }
+    if wall_mode.0 {
+        world_storage.set_wall(target, Block::Air);
+    } else {
+        world_storage.set_block(target, Block::Air);
+    }
+    reload_event.send(ReloadChunk(ChunkPos::from_block_pos(target)));
+    mining_progress.reset();
+}
+
+pub fn update_crack_overlay(
+    asset_server: Res<AssetServer>,
+    mining_progress: Res<MiningProgress>,
+    mut overlay_query: Query<(&mut Transform, &mut Handle<Image>, &mut Visibility), With<CrackOverlay>>,
+) {
+    let (mut transform, mut texture, mut visibility) = overlay_query.single_mut();
+
+    let Some(target) = mining_progress.target else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    if mining_progress.progress <= 0.0 {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+
+    let stage = ((mining_progress.progress * CRACK_STAGES as f32) as usize).min(CRACK_STAGES - 1);
+    let handle = asset_server.load(format!("cracks/crack_{}.png", stage));
+    if *texture != handle {
+        *texture = handle;
+    }
+    transform.translation = (target.as_vec2() * 8.0).extend(10.0);
+    *visibility = Visibility::Visible;
+}
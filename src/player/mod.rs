@@ -1,36 +1,73 @@
 use bevy::prelude::*;
 
-use crate::states::GameState;
+use crate::{physics, states::GameState};
 
 use self::camera::CursorPosition;
 
 pub mod camera;
+pub mod inventory;
+pub mod mining;
 pub mod player;
+pub mod target;
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(CursorPosition(IVec2::ZERO));
+        app.init_resource::<camera::CameraSettings>();
+        app.init_resource::<camera::Zoom>();
+        app.insert_resource(target::Reach(6.0));
+        app.init_resource::<target::BlockTarget>();
+        app.init_resource::<target::WallMode>();
+        app.init_resource::<mining::MiningProgress>();
+        app.init_resource::<inventory::InventoryOpen>();
+        app.init_resource::<player::MovementSettings>();
 
         app.add_systems(
             OnEnter(GameState::InGame),
-            (camera::spawn_camera, player::spawn_player).chain(),
+            (camera::spawn_camera, player::spawn_player, mining::spawn_crack_overlay).chain(),
+        );
+
+        app.add_systems(
+            FixedUpdate,
+            player::movement
+                .before(physics::apply_gravity)
+                .run_if(in_state(GameState::InGame)),
         );
 
         app.add_systems(
             Update,
             (
-                player::movement,
+                player::read_movement_input,
                 player::update_positions,
-                camera::follow_player,
+                // after the player's transform is interpolated for this frame
+                camera::follow_player.after(physics::interpolate_transforms),
                 camera::update_cursor_position,
                 camera::zoom,
+                target::update_wall_mode,
+                target::update_target,
+                target::draw_target,
+                camera::draw_gamepad_cursor,
+                mining::mine,
+                mining::update_crack_overlay,
                 player::mouse_input,
                 player::mouse_attack,
             )
                 .run_if(in_state(GameState::InGame))
                 .chain(),
         );
+
+        app.add_systems(
+            Update,
+            (
+                inventory::select_hotbar,
+                inventory::toggle_inventory,
+                inventory::hotbar_ui,
+                inventory::inventory_ui,
+            )
+                .run_if(in_state(GameState::InGame))
+                .chain(),
+        );
     }
 }
@@ -1,68 +1,207 @@
-use std::ops::Div;
-
-use bevy::{math::vec3, prelude::*};
+use bevy::{math::{vec2, vec3}, prelude::*};
+use bevy_egui::EguiContexts;
 
 use crate::{
-    entities::{arrow::SpawnArrow, item::SpawnItem},
-    physics::{Rigidbody, Velocity},
+    controls::{gamepad::GamepadInput, Action},
+    entities::{arrow::SpawnArrow, health::Health, item::Collector},
+    items::tool::ToolKind,
+    physics::{contacts::CollisionLayers, Collider, NoClip, PhysicsMaterial, Rigidbody, Velocity},
     world::{
         block::Block,
         chunks::{ReloadChunk, ReloadChunks},
         position::ChunkPos,
+        save::SavedPlayer,
+        state::{BlockState, StateProperty},
         storage::WorldStorage,
+        tile_entity::TileEntities,
     },
 };
 
-use super::camera::CursorPosition;
+use super::{
+    camera::CursorPosition,
+    inventory::Inventory,
+    mining::MiningPower,
+    target::{BlockTarget, WallMode},
+};
+
+const PLAYER_SIZE: Vec2 = Vec2::new(8.0, 16.0);
+
+/// size of the area a sword swing hits, in pixels
+const SWORD_RANGE: f32 = 16.0;
+/// share of the usual acceleration left on the most slippery ground
+const MIN_GRIP: f32 = 0.1;
 
 #[derive(Component)]
 pub struct Player;
 
-pub fn spawn_player(mut commands: Commands) {
+#[derive(Resource)]
+pub struct MovementSettings {
+    pub max_speed: f32,
+    pub acceleration: f32,
+    pub deceleration: f32,
+    pub jump_velocity: f32,
+    /// vertical velocity is multiplied by this when jump is released while still rising
+    pub jump_cut: f32,
+    /// seconds after walking off a ledge during which jumping still works
+    pub coyote_time: f32,
+    /// seconds a jump press is remembered before landing
+    pub jump_buffer: f32,
+    pub fly_speed: f32,
+}
+
+impl Default for MovementSettings {
+    fn default() -> Self {
+        Self {
+            max_speed: 128.0,
+            acceleration: 1024.0,
+            deceleration: 1536.0,
+            jump_velocity: 200.0,
+            jump_cut: 0.5,
+            coyote_time: 0.1,
+            jump_buffer: 0.1,
+            fly_speed: 256.0,
+        }
+    }
+}
+
+/// seconds since the player was last grounded and since jump was last pressed
+#[derive(Component)]
+pub struct JumpState {
+    pub since_grounded: f32,
+    pub since_pressed: f32,
+}
+
+pub fn spawn_player(mut commands: Commands, saved_player: Option<Res<SavedPlayer>>) {
+    let mut inventory = Inventory::new();
+    let translation = match saved_player {
+        Some(saved_player) => {
+            for (slot, saved_slot) in inventory.slots.iter_mut().zip(saved_player.inventory.iter()) {
+                *slot = *saved_slot;
+            }
+            saved_player.position.extend(20.0)
+        }
+        None => vec3(3000.0, 5000.0, 20.0),
+    };
+
     commands.spawn((
         SpriteBundle {
             sprite: Sprite {
                 color: Color::rgb(0.0, 0.0, 1.0),
+                custom_size: Some(PLAYER_SIZE),
                 ..default()
             },
             transform: Transform {
-                translation: vec3(3000.0, 5000.0, 20.0),
+                translation,
                 // translation: vec3(100.0, 8.0*32.0*8.0, 20.0),
-                scale: vec3(8.0, 16.0, 8.0),
                 ..default()
             },
             ..default()
         },
         Player,
-        Rigidbody {
-            grounded: false,
-            friction: false,
+        MiningPower(1.0),
+        inventory,
+        Collector,
+        Rigidbody::default(),
+        // movement handles grip itself, scaled by `Rigidbody::ground_friction`
+        PhysicsMaterial { friction: 0.0, restitution: 0.0, drag: 0.0 },
+        Collider::new(PLAYER_SIZE),
+        CollisionLayers::new(
+            CollisionLayers::PLAYER,
+            CollisionLayers::MOB | CollisionLayers::ITEM | CollisionLayers::TRIGGER,
+        ),
+        JumpState {
+            since_grounded: f32::INFINITY,
+            since_pressed: f32::INFINITY,
         },
+        MovementInput::default(),
         ChunkPos(IVec2::ZERO),
         Velocity(Vec2::ZERO),
     ));
 }
 
-pub fn movement(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut player_query: Query<&mut Velocity, With<Player>>,
+/// what the player asked for since the last physics step, read every frame and
+/// applied on the fixed step so movement does not depend on the frame rate
+#[derive(Component, Default)]
+pub struct MovementInput {
+    pub direction: Vec2,
+    /// kept until a physics step consumes them, frames can pass without one
+    pub jump_pressed: bool,
+    pub jump_released: bool,
+}
+
+pub fn read_movement_input(
+    actions: Res<Input<Action>>,
+    gamepad_input: Res<GamepadInput>,
+    mut player_query: Query<&mut MovementInput, With<Player>>,
 ) {
-    let mut velocity = player_query.single_mut();
+    let mut input = player_query.single_mut();
 
-    velocity.0.x = 0.0;
-    // velocity.0.y = 0.0;
-    if keyboard_input.pressed(KeyCode::A) {
-        velocity.0.x = -128.0;
+    let mut direction = Vec2::ZERO;
+    if actions.pressed(Action::MoveLeft) {
+        direction.x -= 1.0;
+    }
+    if actions.pressed(Action::MoveRight) {
+        direction.x += 1.0;
     }
-    if keyboard_input.pressed(KeyCode::D) {
-        velocity.0.x = 128.0;
+    if actions.pressed(Action::MoveUp) {
+        direction.y += 1.0;
     }
-    if keyboard_input.pressed(KeyCode::W) {
-        velocity.0.y = 128.0;
+    if actions.pressed(Action::MoveDown) {
+        direction.y -= 1.0;
     }
-    if keyboard_input.pressed(KeyCode::S) {
-        velocity.0.y = -128.0;
+    // the stick only counts when no keys are held
+    if direction == Vec2::ZERO {
+        direction = gamepad_input.movement;
     }
+
+    input.direction = direction;
+    input.jump_pressed |= actions.just_pressed(Action::Jump);
+    input.jump_released |= actions.just_released(Action::Jump);
+}
+
+pub fn movement(
+    settings: Res<MovementSettings>,
+    mut player_query: Query<(&mut Velocity, &mut Rigidbody, &mut JumpState, &mut MovementInput, Option<&NoClip>), With<Player>>,
+    fixed_time: Res<FixedTime>,
+) {
+    let (mut velocity, mut rigidbody, mut jump, mut input, noclip) = player_query.single_mut();
+    let delta = fixed_time.period.as_secs_f32();
+    let direction = input.direction;
+    let jump_pressed = std::mem::take(&mut input.jump_pressed);
+    let jump_released = std::mem::take(&mut input.jump_released);
+
+    if noclip.is_some() {
+        velocity.0 = direction * settings.fly_speed;
+        return;
+    }
+
+    // holding down drops through platforms
+    rigidbody.drop_through = direction.y < -0.5;
+
+    let target_speed = direction.x * settings.max_speed;
+    let rate = if direction.x != 0.0 { settings.acceleration } else { settings.deceleration };
+    // slippery ground makes starting and stopping slower
+    let grip = if rigidbody.grounded { rigidbody.ground_friction.clamp(MIN_GRIP, 1.0) } else { 1.0 };
+    let rate = rate * grip;
+    velocity.0.x = move_towards(velocity.0.x, target_speed, rate * delta);
+
+    jump.since_grounded = if rigidbody.grounded { 0.0 } else { jump.since_grounded + delta };
+    jump.since_pressed = if jump_pressed { 0.0 } else { jump.since_pressed + delta };
+
+    if jump.since_pressed <= settings.jump_buffer && jump.since_grounded <= settings.coyote_time {
+        velocity.0.y = settings.jump_velocity;
+        jump.since_pressed = f32::INFINITY;
+        jump.since_grounded = f32::INFINITY;
+    }
+
+    // letting go early makes for a shorter jump
+    if jump_released && velocity.0.y > 0.0 {
+        velocity.0.y *= settings.jump_cut;
+    }
+}
+
+fn move_towards(current: f32, target: f32, max_delta: f32) -> f32 {
+    current + (target - current).clamp(-max_delta, max_delta)
 }
 
 pub fn update_positions(
@@ -83,54 +222,248 @@ pub fn update_positions(
 }
 
 pub fn mouse_input(
-    cursor_pos: Res<CursorPosition>,
-    mouse_input: Res<Input<MouseButton>>,
+    mut contexts: EguiContexts,
+    block_target: Res<BlockTarget>,
+    wall_mode: Res<WallMode>,
+    actions: Res<Input<Action>>,
+    mut player_query: Query<(&Transform, &mut Inventory), With<Player>>,
+    body_query: Query<(&Transform, &Collider), With<Rigidbody>>,
+    tile_entities: Res<TileEntities>,
     mut world_storage: ResMut<WorldStorage>,
     mut reload_event: EventWriter<ReloadChunk>,
-    mut item_event: EventWriter<SpawnItem>,
+    mut interacting: Local<bool>,
 ) {
-    if mouse_input.pressed(MouseButton::Left) {
-        let block = world_storage.get_block(cursor_pos.0).unwrap();
-        if block == Block::Air {
+    // interact shares its button with place by default, a click that uses a tile entity
+    // places nothing until it is let go, even once the cursor leaves the tile
+    if actions.just_pressed(Action::Interact) {
+        *interacting = block_target.solid().is_some_and(|target| tile_entities.get(target).is_some());
+    }
+    if !actions.pressed(Action::Interact) {
+        *interacting = false;
+    }
+
+    if *interacting || !actions.pressed(Action::Place) || actions.pressed(Action::Mine) {
+        return;
+    }
+    if contexts.ctx_mut().wants_pointer_input() {
+        return;
+    }
+
+    let Some(target) = block_target.empty() else { return; };
+    let (player_transform, mut inventory) = player_query.single_mut();
+
+    if wall_mode.0 {
+        let Some(wall) = inventory.selected_stack().and_then(|stack| stack.item.places_wall()) else { return; };
+        if !can_place_wall(&mut world_storage, target) {
             return;
-        };
-        let chunk_pos = ChunkPos::from_block_pos(cursor_pos.0);
-
-        item_event.send(SpawnItem {
-            // position: cursor_pos.0.as_vec2() * 8.0,
-            position: Vec2 {
-                x: (cursor_pos.0.x * 8) as f32,
-                y: (cursor_pos.0.y * 8) as f32,
-            },
-            block, // hehe crash here!, fix l8r B)
-        });
-        world_storage.set_block(cursor_pos.0, Block::Air);
-        reload_event.send(ReloadChunk(chunk_pos));
-    } else if mouse_input.pressed(MouseButton::Right) {
-        let chunk_pos = ChunkPos::from_block_pos(cursor_pos.0);
-        world_storage.set_block(cursor_pos.0, Block::Dirt);
-        reload_event.send(ReloadChunk(chunk_pos));
+        }
+        world_storage.set_wall(target, wall);
+        inventory.remove_selected(1);
+        reload_event.send(ReloadChunk(ChunkPos::from_block_pos(target)));
+        return;
+    }
+
+    let Some(block) = inventory.selected_stack().and_then(|stack| stack.item.places_block()) else { return; };
+    if !can_place(&mut world_storage, target) {
+        return;
+    }
+
+    // bodies only block placing tiles they would collide with
+    let tile_center = target.as_vec2() * 8.0;
+    let overlaps_body = block.is_solid() && body_query.iter().any(|(transform, collider)| {
+        collider.overlaps_box(transform.translation.truncate(), tile_center, vec2(8.0, 8.0))
+    });
+    if overlaps_body {
+        return;
+    }
+
+    world_storage.set_block(target, block);
+    if block.state_properties().contains(&StateProperty::Orientation) {
+        let orientation = slope_orientation(&world_storage, target, player_transform.translation.x);
+        world_storage.set_state(target, BlockState::default().with(block, StateProperty::Orientation, orientation));
+    }
+    inventory.remove_selected(1);
+    reload_event.send(ReloadChunk(ChunkPos::from_block_pos(target)));
+}
+
+/// slopes rise towards a lone solid neighbour, otherwise away from the player so they can walk up
+fn slope_orientation(world_storage: &WorldStorage, block_pos: IVec2, player_x: f32) -> u16 {
+    let left = world_storage.is_solid(block_pos + IVec2::NEG_X);
+    let right = world_storage.is_solid(block_pos + IVec2::X);
+    match (left, right) {
+        (false, true) => 0,
+        (true, false) => 1,
+        _ if player_x <= block_pos.x as f32 * 8.0 => 0,
+        _ => 1,
     }
 }
 
+/// blocks go on replaceable cells that are backed by a wall or touch a solid block
+fn can_place(world_storage: &mut WorldStorage, block_pos: IVec2) -> bool {
+    if !world_storage.get_block(block_pos).is_some_and(|block| block.is_replaceable()) {
+        return false;
+    }
+    if world_storage.get_wall(block_pos).is_some_and(|wall| wall != Block::Air) {
+        return true;
+    }
+
+    [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y].iter().any(|offset| {
+        world_storage.get_block(block_pos + *offset).is_some_and(|block| block.is_solid())
+    })
+}
+
+/// walls go on empty wall cells next to another wall or a solid block
+fn can_place_wall(world_storage: &mut WorldStorage, block_pos: IVec2) -> bool {
+    if world_storage.get_wall(block_pos) != Some(Block::Air) {
+        return false;
+    }
+
+    [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y].iter().any(|offset| {
+        world_storage.get_wall(block_pos + *offset).is_some_and(|wall| wall != Block::Air)
+            || world_storage.get_block(block_pos + *offset).is_some_and(|block| block != Block::Air)
+    })
+}
+
 pub fn mouse_attack(
-    player_query: Query<&Transform, With<Player>>,
+    mut contexts: EguiContexts,
+    mut player_query: Query<(&Transform, &mut Inventory), With<Player>>,
+    mut target_query: Query<(&Transform, &Collider, &mut Health), Without<Player>>,
     cursor_pos: Res<CursorPosition>,
-    mouse_input: Res<Input<MouseButton>>,
+    actions: Res<Input<Action>>,
     mut arrow_event: EventWriter<SpawnArrow>,
+    mut cooldown: Local<f32>,
+    time: Res<Time>,
 ) {
-    if !mouse_input.just_pressed(MouseButton::Middle) {
+    *cooldown -= time.delta_seconds();
+    if *cooldown > 0.0 || !actions.pressed(Action::Attack) || contexts.ctx_mut().wants_pointer_input() {
         return;
     };
 
-    let player_transform = player_query.single();
-    let block_pos = player_transform.translation.truncate().as_ivec2().div(8);
-    let diff_pos = cursor_pos.0 - block_pos;
-    let norm = diff_pos.as_vec2().normalize();
+    let (player_transform, mut inventory) = player_query.single_mut();
+    let Some(tool) = inventory.selected_stack().and_then(|stack| stack.item.tool()) else { return; };
+    if !tool.kind.is_weapon() {
+        return;
+    }
+    *cooldown = 1.0 / tool.speed;
 
-    arrow_event.send(SpawnArrow {
-        position: player_transform.translation.truncate(),
-        velocity: norm * 256.0,
-    });
+    let position = player_transform.translation.truncate();
+    let direction = (cursor_pos.0.as_vec2() * 8.0 - position).normalize_or_zero();
+
+    match tool.kind {
+        ToolKind::Bow => {
+            arrow_event.send(SpawnArrow {
+                position,
+                velocity: direction * 256.0,
+                damage: tool.damage,
+            });
+        }
+        ToolKind::Sword => {
+            // hits everything in a square in front of the player
+            let hit_center = position + direction * SWORD_RANGE / 2.0;
+            for (transform, collider, mut health) in target_query.iter_mut() {
+                if collider.overlaps_box(transform.translation.truncate(), hit_center, Vec2::splat(SWORD_RANGE)) {
+                    health.0 -= tool.damage;
+                }
+            }
+        }
+        _ => {}
+    }
+    inventory.wear_selected();
 }
 
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec2;
+
+    use crate::{physics::{PhysicsPlugin, PhysicsPosition, GRAVITY}, states::GameState};
+
+    use super::*;
+
+    /// standing on a stone floor whose top is at y = 4
+    const START: Vec2 = Vec2::new(100.0, 12.0);
+
+    fn app() -> App {
+        let mut world_storage = WorldStorage::new();
+        for x in 0..64 {
+            world_storage.set_block(ivec2(x, 0), Block::Stone);
+        }
+
+        let mut app = App::new();
+        app.add_plugins(PhysicsPlugin);
+        app.insert_resource(State::new(GameState::InGame));
+        app.insert_resource(world_storage);
+        app.init_resource::<MovementSettings>();
+        app.add_systems(FixedUpdate, movement.before(crate::physics::apply_gravity));
+
+        app.world.spawn((
+            Player,
+            Transform::from_translation(START.extend(0.0)),
+            PhysicsPosition { current: START, previous: START },
+            Rigidbody::default(),
+            PhysicsMaterial { friction: 0.0, restitution: 0.0, drag: 0.0 },
+            Collider::new(PLAYER_SIZE),
+            JumpState { since_grounded: f32::INFINITY, since_pressed: f32::INFINITY },
+            MovementInput { direction: Vec2::X, ..default() },
+            Velocity(Vec2::ZERO),
+        ));
+        app
+    }
+
+    fn step(app: &mut App, steps: usize) -> Vec2 {
+        for _ in 0..steps {
+            app.world.run_schedule(FixedUpdate);
+        }
+        app.world.query::<&PhysicsPosition>().single(&app.world).current
+    }
+
+    #[test]
+    fn walking_is_deterministic() {
+        let position = step(&mut app(), 60);
+
+        // one step at full acceleration while not yet grounded, then 0.8 grip on stone
+        // until the 128 px/s top speed is reached on the tenth step
+        let settings = MovementSettings::default();
+        let delta = 1.0 / 60.0;
+        let mut speed = 0.0;
+        let mut distance = 0.0;
+        for i in 0..60 {
+            let grip = if i == 0 { 1.0 } else { 0.8 };
+            speed = move_towards(speed, settings.max_speed, settings.acceleration * grip * delta);
+            distance += speed * delta;
+        }
+        assert!((position.x - (START.x + distance)).abs() < 0.01, "{} != {}", position.x, START.x + distance);
+        assert!((position.y - START.y).abs() < 0.001, "{} != {}", position.y, START.y);
+
+        // the same steps always end in the same place
+        assert_eq!(step(&mut app(), 60), position);
+    }
+
+    #[test]
+    fn blocks_are_not_placed_against_pressure_plates() {
+        let mut world_storage = WorldStorage::new();
+        world_storage.set_block(ivec2(10, 10), Block::PressurePlate);
+        assert!(!can_place(&mut world_storage, ivec2(11, 10)));
+
+        world_storage.set_block(ivec2(10, 10), Block::Stone);
+        assert!(can_place(&mut world_storage, ivec2(11, 10)));
+    }
+
+    #[test]
+    fn jump_lands_where_it_started() {
+        let mut app = app();
+        step(&mut app, 1);
+        let mut query = app.world.query::<&mut MovementInput>();
+        let mut input = query.single_mut(&mut app.world);
+        input.direction = Vec2::ZERO;
+        input.jump_pressed = true;
+
+        let peak = (0..30).map(|_| step(&mut app, 1).y).fold(f32::MIN, f32::max);
+        let settings = MovementSettings::default();
+        let expected_peak = START.y + settings.jump_velocity.powi(2) / (2.0 * GRAVITY);
+        assert!((peak - expected_peak).abs() < 4.0, "{} != {}", peak, expected_peak);
+
+        let position = step(&mut app, 60);
+        assert!((position.y - START.y).abs() < 0.001, "{} != {}", position.y, START.y);
+        assert!(app.world.query::<&Rigidbody>().single(&app.world).grounded);
+    }
+}
@@ -0,0 +1,72 @@
+use bevy::{math::vec2, prelude::*};
+
+use crate::{controls::Action, items::tool::ToolKind, world::storage::WorldStorage};
+
+use super::{camera::CursorPosition, inventory::Inventory, player::Player};
+
+/// how far from the player blocks can be interacted with, in blocks
+#[derive(Resource)]
+pub struct Reach(pub f32);
+
+/// the block the player is aiming at: the first solid block between the player and the cursor,
+/// or the cursor tile itself if nothing is in the way
+#[derive(Resource, Default)]
+pub struct BlockTarget {
+    pub pos: IVec2,
+    /// whether `pos` is a solid block that blocked the line of sight
+    pub hit: bool,
+    pub in_reach: bool,
+}
+
+impl BlockTarget {
+    /// solid block that can be mined or used
+    pub fn solid(&self) -> Option<IVec2> {
+        (self.hit && self.in_reach).then_some(self.pos)
+    }
+
+    /// empty tile under the cursor that can be built in
+    pub fn empty(&self) -> Option<IVec2> {
+        (!self.hit && self.in_reach).then_some(self.pos)
+    }
+}
+
+/// whether mining and placing act on walls instead of blocks,
+/// on while holding the wall mode key or a hammer
+#[derive(Resource, Default)]
+pub struct WallMode(pub bool);
+
+pub fn update_wall_mode(
+    actions: Res<Input<Action>>,
+    player_query: Query<&Inventory, With<Player>>,
+    mut wall_mode: ResMut<WallMode>,
+) {
+    let inventory = player_query.single();
+    let holds_hammer = inventory.selected_stack()
+        .and_then(|stack| stack.item.tool())
+        .is_some_and(|tool| tool.kind == ToolKind::Hammer);
+    wall_mode.0 = actions.pressed(Action::WallMode) || holds_hammer;
+}
+
+pub fn update_target(
+    cursor_pos: Res<CursorPosition>,
+    reach: Res<Reach>,
+    world_storage: Res<WorldStorage>,
+    player_query: Query<&Transform, With<Player>>,
+    mut block_target: ResMut<BlockTarget>,
+) {
+    let player_transform = player_query.single();
+    let player_pos = player_transform.translation.truncate() / 8.0;
+
+    let hit = world_storage.raycast(player_pos, cursor_pos.0.as_vec2());
+    block_target.pos = hit.unwrap_or(cursor_pos.0);
+    block_target.hit = hit.is_some() || world_storage.is_occupied(cursor_pos.0);
+    block_target.in_reach = block_target.pos.as_vec2().distance(player_pos) <= reach.0;
+}
+
+pub fn draw_target(
+    mut gizmos: Gizmos,
+    block_target: Res<BlockTarget>,
+) {
+    let color = if block_target.in_reach { Color::WHITE } else { Color::RED };
+    gizmos.rect_2d(block_target.pos.as_vec2() * 8.0, 0.0, vec2(8.0, 8.0), color);
+}
@@ -1,4 +1,10 @@
-#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+use serde::Deserialize;
+
+use crate::{items::{tool::ToolKind, ItemId}, physics::PhysicsMaterial};
+
+use super::{chunks::BLOCK_SIZE, state::{BlockState, StateProperty}};
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash, Deserialize)]
 #[repr(u32)]
 #[allow(dead_code)]
 pub enum Block {
@@ -7,18 +13,32 @@ pub enum Block {
     Dirt,
     Stone,
     Border,
+    Chest,
+    Workbench,
+    PressurePlate,
+    Platform,
+    StoneSlab,
+    StoneSlope,
+    Ice,
 }
 
 impl Block {
-    #[allow(dead_code)]
-    pub fn from(block: u32) -> Self {
+    /// `None` for ids no block has, e.g. from a newer or corrupt save
+    pub fn from(block: u32) -> Option<Self> {
         match block {
-            0 => Self::Air,
-            1 => Self::Grass,
-            2 => Self::Dirt,
-            3 => Self::Stone,
-            4 => Self::Border,
-            _ => Self::Air,
+            0 => Some(Self::Air),
+            1 => Some(Self::Grass),
+            2 => Some(Self::Dirt),
+            3 => Some(Self::Stone),
+            4 => Some(Self::Border),
+            5 => Some(Self::Chest),
+            6 => Some(Self::Workbench),
+            7 => Some(Self::PressurePlate),
+            8 => Some(Self::Platform),
+            9 => Some(Self::StoneSlab),
+            10 => Some(Self::StoneSlope),
+            11 => Some(Self::Ice),
+            _ => None,
         }
     }
 
@@ -29,16 +49,184 @@ impl Block {
             Block::Dirt => true,
             Block::Stone => true,
             Block::Border => true,
+            Block::Chest => false,
+            Block::Workbench => false,
+            Block::PressurePlate => false,
+            Block::Platform => false,
+            Block::StoneSlab => false,
+            Block::StoneSlope => false,
+            Block::Ice => true,
+        }
+    }
+
+    /// item dropped when the block is broken
+    pub fn drop_item(&self) -> Option<ItemId> {
+        match self {
+            Block::Air => None,
+            Block::Grass => Some(ItemId::Dirt),
+            Block::Dirt => Some(ItemId::Dirt),
+            Block::Stone => Some(ItemId::Stone),
+            Block::Border => None,
+            Block::Chest => Some(ItemId::Chest),
+            Block::Workbench => Some(ItemId::Workbench),
+            Block::PressurePlate => Some(ItemId::PressurePlate),
+            Block::Platform => Some(ItemId::Platform),
+            Block::StoneSlab => Some(ItemId::StoneSlab),
+            Block::StoneSlope => Some(ItemId::StoneSlope),
+            Block::Ice => Some(ItemId::Ice),
+        }
+    }
+
+    /// blocks bodies collide with, everything else can be walked through
+    pub fn is_solid(&self) -> bool {
+        !matches!(self, Block::Air | Block::PressurePlate)
+    }
+
+    /// what bodies collide with inside the tile
+    pub fn shape(&self, state: BlockState) -> BlockShape {
+        match self {
+            Block::Air => BlockShape::Empty,
+            Block::PressurePlate => BlockShape::Empty,
+            Block::Platform => BlockShape::Platform,
+            Block::StoneSlab => BlockShape::Slab,
+            Block::StoneSlope => match state.get(*self, StateProperty::Orientation) {
+                Some(1) => BlockShape::SlopeUpLeft,
+                _ => BlockShape::SlopeUpRight,
+            },
+            _ => BlockShape::Full,
+        }
+    }
+
+    /// how bodies slide and bounce on the block, and how much it slows bodies inside it
+    pub fn material(&self) -> PhysicsMaterial {
+        match self {
+            Block::Ice => PhysicsMaterial { friction: 0.05, ..PhysicsMaterial::default() },
+            Block::Stone | Block::StoneSlab | Block::StoneSlope => PhysicsMaterial { friction: 0.8, ..PhysicsMaterial::default() },
+            _ => PhysicsMaterial::default(),
+        }
+    }
+
+    /// blocks that can be placed over
+    pub fn is_replaceable(&self) -> bool {
+        matches!(self, Block::Air)
+    }
+
+    /// blocks that get a tile entity spawned for them while their chunk is loaded
+    pub fn has_tile_entity(&self) -> bool {
+        matches!(self, Block::Chest | Block::PressurePlate)
+    }
+
+    /// blocks that unlock extra crafting recipes when the player stands near them
+    pub fn is_station(&self) -> bool {
+        matches!(self, Block::Workbench)
+    }
+
+    /// seconds to break with a mining power of 1
+    pub fn hardness(&self) -> f32 {
+        match self {
+            Block::Air => 0.0,
+            Block::Grass => 0.4,
+            Block::Dirt => 0.5,
+            Block::Stone => 1.5,
+            Block::Border => f32::INFINITY,
+            Block::Chest => 0.8,
+            Block::Workbench => 0.8,
+            Block::PressurePlate => 0.3,
+            Block::Platform => 0.3,
+            Block::StoneSlab => 0.8,
+            Block::StoneSlope => 0.8,
+            Block::Ice => 0.5,
+        }
+    }
+
+    /// tool that speeds up breaking this block
+    pub fn tool(&self) -> Option<ToolKind> {
+        match self {
+            Block::Stone => Some(ToolKind::Pickaxe),
+            Block::Chest => Some(ToolKind::Axe),
+            Block::Workbench => Some(ToolKind::Axe),
+            Block::PressurePlate => Some(ToolKind::Pickaxe),
+            Block::StoneSlab => Some(ToolKind::Pickaxe),
+            Block::StoneSlope => Some(ToolKind::Pickaxe),
+            Block::Ice => Some(ToolKind::Pickaxe),
+            _ => None,
         }
     }
 
-    pub fn texture_path(&self) -> &str {
+    /// lowest tool tier that gets a speed bonus on this block
+    pub fn tier(&self) -> u32 {
         match self {
-            Block::Air => "tiles/air.png",
-            Block::Grass => "tiles/grass.png",
-            Block::Dirt => "tiles/dirt.png",
-            Block::Stone => "tiles/stone.png",
-            Block::Border => "tiles/border.png",
+            Block::Stone | Block::StoneSlab | Block::StoneSlope => 1,
+            _ => 0,
         }
     }
+
+    /// state properties stored in this block's `BlockState`, in bit order
+    pub fn state_properties(&self) -> &'static [StateProperty] {
+        match self {
+            Block::Grass => &[StateProperty::GrowthStage],
+            Block::PressurePlate => &[StateProperty::Power],
+            Block::StoneSlope => &[StateProperty::Orientation],
+            _ => &[],
+        }
+    }
+
+    /// tileset index, picking a variant texture based on the block state
+    pub fn texture_index(&self, state: BlockState) -> u32 {
+        match self {
+            Block::Grass => match state.get(*self, StateProperty::GrowthStage) {
+                Some(0..=1) => 5,
+                _ => 1,
+            },
+            Block::Chest => 6,
+            Block::Workbench => 7,
+            Block::PressurePlate => match state.get(*self, StateProperty::Power) {
+                Some(1..) => 9,
+                _ => 8,
+            },
+            Block::Platform => 10,
+            Block::StoneSlab => 11,
+            Block::StoneSlope => match state.get(*self, StateProperty::Orientation) {
+                Some(1) => 13,
+                _ => 12,
+            },
+            Block::Ice => 14,
+            _ => *self as u32,
+        }
+    }
+}
+
+/// collision shape of a block inside its tile
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
+pub enum BlockShape {
+    Empty,
+    Full,
+    /// one way, only stops bodies coming down onto it from above
+    Platform,
+    /// bottom half of the tile
+    Slab,
+    /// 45 degree ramp, high on the right
+    SlopeUpRight,
+    /// 45 degree ramp, high on the left
+    SlopeUpLeft,
+}
+
+impl BlockShape {
+    /// height of the surface above the tile's bottom edge, under a box spanning `left..right`
+    /// measured from the tile's left edge
+    pub fn height(&self, left: f32, right: f32) -> f32 {
+        let size = BLOCK_SIZE as f32;
+        match self {
+            BlockShape::Empty => 0.0,
+            BlockShape::Full | BlockShape::Platform => size,
+            BlockShape::Slab => size / 2.0,
+            BlockShape::SlopeUpRight => right.clamp(0.0, size),
+            BlockShape::SlopeUpLeft => (size - left).clamp(0.0, size),
+        }
+    }
+
+    /// shapes bodies walk up onto instead of bumping into
+    pub fn is_step(&self) -> bool {
+        matches!(self, BlockShape::Slab | BlockShape::SlopeUpRight | BlockShape::SlopeUpLeft)
+    }
 }
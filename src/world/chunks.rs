@@ -5,14 +5,24 @@ use bevy_tileset::prelude::{Tilesets, Tileset};
 
 use crate::player::player::Player;
 
-use super::{position::ChunkPos, storage::{ChunkData, WorldStorage}, block::Block};
+use super::{position::ChunkPos, storage::{ChunkData, WorldStorage}, block::{Block, BlockShape}};
 
 pub const BLOCK_SIZE: i32 = 8;
 pub const CHUNK_SIZE: i32 = 32;
 
 #[derive(Resource)]
 // pub struct Colls(pub HashSet<(IVec2, i32)>);
-pub struct Colls(pub HashMap<ChunkPos, HashSet<(IVec2, i32)>>);
+pub struct Colls(pub HashMap<ChunkPos, HashSet<(IVec2, i32, BlockShape)>>);
+
+/// how many chunks around the player's chunk are loaded on each axis, follows the camera zoom
+#[derive(Resource)]
+pub struct ChunkLoadRadius(pub IVec2);
+
+impl Default for ChunkLoadRadius {
+    fn default() -> Self {
+        Self(ivec2(2, 1))
+    }
+}
 
 #[derive(Resource, Default)]
 pub struct LoadedChunks(HashMap<ChunkPos, (Entity, Entity)>);
@@ -23,12 +33,20 @@ pub struct ReloadChunks;
 #[derive(Event)]
 pub struct ReloadChunk(pub ChunkPos);
 
+/// a block changed state without changing shape, so only its texture needs redrawing
+#[derive(Event)]
+pub struct RedrawTile(pub IVec2);
+
 #[allow(dead_code)]
 impl LoadedChunks {
     pub fn get_chunk(&self, chunk_pos: ChunkPos) -> Option<&(Entity, Entity)> {
         self.0.get(&chunk_pos)
     }
 
+    pub fn chunk_positions(&self) -> impl Iterator<Item = &ChunkPos> {
+        self.0.keys()
+    }
+
     pub fn add_chunk(&mut self, chunk_pos: ChunkPos, chunk_entity: Entity, wall_chunk_entity: Entity) {
         if !chunk_pos.in_bounds() {
             warn!("could not load chunk that is out of bounds: {}", chunk_pos.0);
@@ -48,6 +66,7 @@ pub fn spawn_chunks_near_player(
     mut loaded_chunks: ResMut<LoadedChunks>,
     mut colls: ResMut<Colls>,
     world_storage: Res<WorldStorage>,
+    load_radius: Res<ChunkLoadRadius>,
     player_query: Query<&ChunkPos, With<Player>>,
     reload_event: EventReader<ReloadChunks>,
 ) {
@@ -56,8 +75,8 @@ pub fn spawn_chunks_near_player(
     let Ok(player_chunk_pos) = player_query.get_single() else { return };
 
     despawn_all_chunks(&mut commands, &mut loaded_chunks, &mut colls);
-    for y in -1..=1 { //1 / 4
-        for x in -2..=2 { //2 / 4
+    for y in -load_radius.0.y..=load_radius.0.y {
+        for x in -load_radius.0.x..=load_radius.0.x {
             let chunk_pos_raw = ivec2(x + player_chunk_pos.0.x as i32, y + player_chunk_pos.0.y as i32);
             let chunk_pos = ChunkPos(chunk_pos_raw);
             if !chunk_pos.in_bounds() { continue; };
@@ -94,6 +113,30 @@ pub fn reload_chunk(
     }
 }
 
+/// swaps the texture of single tiles instead of respawning their whole chunk
+pub fn redraw_tiles(
+    mut redraw_event: EventReader<RedrawTile>,
+    loaded_chunks: Res<LoadedChunks>,
+    world_storage: Res<WorldStorage>,
+    tilemap_query: Query<&TileStorage>,
+    mut tile_query: Query<&mut TileTextureIndex>,
+) {
+    for ev in redraw_event.iter() {
+        let chunk_pos = ChunkPos::from_block_pos(ev.0);
+        let Some((chunk_entity, _)) = loaded_chunks.get_chunk(chunk_pos) else { continue; };
+        let Some(chunk_data) = world_storage.get_chunk_data(chunk_pos) else { continue; };
+        // chunks spawned this frame have no tiles yet, they are drawn with the new state anyway
+        let Ok(tile_storage) = tilemap_query.get(*chunk_entity) else { continue; };
+
+        let rel_pos = ivec2(ev.0.x.rem_euclid(CHUNK_SIZE), ev.0.y.rem_euclid(CHUNK_SIZE));
+        let Some(tile_entity) = tile_storage.get(&TilePos { x: rel_pos.x as u32, y: rel_pos.y as u32 }) else { continue; };
+        let Ok(mut texture_index) = tile_query.get_mut(tile_entity) else { continue; };
+        let block = chunk_data.get_block(rel_pos).unwrap();
+        let state = chunk_data.get_state(rel_pos).unwrap();
+        texture_index.0 = block.texture_index(state);
+    }
+}
+
 fn despawn_all_chunks(
     commands: &mut Commands,
     loaded_chunks: &mut ResMut<LoadedChunks>,
@@ -132,39 +175,41 @@ fn add_colls(
     };
 
     // perf: dont clone but reference?
-    let mut hashset: HashSet<(IVec2, i32)> = HashSet::new();
+    let mut hashset: HashSet<(IVec2, i32, BlockShape)> = HashSet::new();
 
     for y in 0..CHUNK_SIZE {
-        let mut s = -1;
-        let mut i = 0;
+        // start and length of the current run
+        let mut run: Option<(i32, i32, BlockShape)> = None;
 
-        // 1d greedy meshing for colls
+        // 1d greedy meshing for colls, runs only merge tiles of the same shape
         for x in 0..CHUNK_SIZE {
             let block = chunk_data.get_block(ivec2(x, y)).unwrap();
-
-            if block != Block::Air { // if solid
-                if s == -1 { // if no start, new start
-                    s = x;
-                }
-
-                i += 1; // increase current coll
-
-                if x == CHUNK_SIZE-1 { // if on last block, treat as air (end and add coll)
-                    let pos = ivec2(s+chunk_pos.0.x*32,y+chunk_pos.0.y*32);
-                    // colls.0.insert((pos, i));
-                    hashset.insert((pos, i));
+            let state = chunk_data.get_state(ivec2(x, y)).unwrap();
+            let shape = block.shape(state);
+
+            // slopes are one tile each, a row of them is a staircase and not a longer ramp
+            let merges = matches!(shape, BlockShape::Full | BlockShape::Platform | BlockShape::Slab);
+            if let Some((s, i, run_shape)) = run {
+                if merges && run_shape == shape {
+                    run = Some((s, i + 1, shape));
+                    continue;
                 }
-            } else { // if air
-                if i == 0 { continue; }; // if no start ignore
-
                 // end and add coll
                 let pos = ivec2(s+chunk_pos.0.x*32,y+chunk_pos.0.y*32);
-                // colls.0.insert((pos, i));
-                hashset.insert((pos, i));
-                s = -1;
-                i = 0;
+                hashset.insert((pos, i, run_shape));
+                run = None;
+            }
+
+            if shape != BlockShape::Empty { // new start
+                run = Some((x, 1, shape));
             }
         }
+
+        // a run reaching the chunk edge ends there
+        if let Some((s, i, run_shape)) = run {
+            let pos = ivec2(s+chunk_pos.0.x*32,y+chunk_pos.0.y*32);
+            hashset.insert((pos, i, run_shape));
+        }
     }
 
     colls.0.insert(chunk_pos, hashset.clone());
@@ -196,6 +241,7 @@ fn spawn_chunk(
             for y in 0..CHUNK_SIZE {
                 for x in 0..CHUNK_SIZE {
                     let block = chunk_data.get_block(ivec2(x, y)).unwrap();
+                    let state = chunk_data.get_state(ivec2(x, y)).unwrap();
                     let (flip_x, flip_y) = if block.should_flip() {
                         chunk_data.get_flip(ivec2(x, y)).unwrap()
                     } else {
@@ -205,7 +251,7 @@ fn spawn_chunk(
                     let tile_pos = TilePos { x: x as u32, y: y as u32 };
                     let tile_entity = builder.spawn(TileBundle {
                         position: tile_pos,
-                        texture_index: TileTextureIndex(block as u32),
+                        texture_index: TileTextureIndex(block.texture_index(state)),
                         tilemap_id: TilemapId(builder.parent_entity()),
                         flip: TileFlip {
                             x: flip_x,
@@ -259,8 +305,10 @@ fn spawn_wall_chunk(
             for y in 0..CHUNK_SIZE {
                 for x in 0..CHUNK_SIZE {
                     let front = chunk_data.get_block(ivec2(x, y)).unwrap();
+                    let front_state = chunk_data.get_state(ivec2(x, y)).unwrap();
 
-                    if front != Block::Air { continue; };
+                    // stays visible behind platforms, slabs and slopes
+                    if front != Block::Air && front.shape(front_state) == BlockShape::Full { continue; };
 
                     let block = chunk_data.get_wall(ivec2(x, y)).unwrap();
                     let (flip_x, flip_y) = if block.should_flip() {
@@ -2,11 +2,14 @@ use bevy::{math::ivec2, prelude::*};
 use noise::{Fbm, NoiseFn, Perlin};
 use rand::prelude::*;
 
-use crate::states::GameState;
+use crate::{items::{ItemId, ItemStack}, states::GameState};
 
 use super::{
     block::Block,
+    save::{self, SaveState},
+    state::{BlockState, StateProperty},
     storage::{WorldStorage, WORLD_BLOCK_SIZE},
+    tile_entity::{Chest, CHEST_SLOTS},
 };
 
 const SURFACE_LENGTH: f64 = 48.0;
@@ -21,19 +24,38 @@ const STONE_THRESHOLD: usize = 5;
 const CAVES_SCALE: f64 = 10.0;
 const CAVES_TRESHOLD: f64 = -0.1;
 
-pub fn generate(mut commands: Commands) {
-    let mut world = WorldStorage::new();
-    let mut rng = thread_rng();
+const ICE_SCALE: f64 = 16.0;
+const ICE_THRESHOLD: f64 = 0.3;
+const ICE_DEPTH: i32 = 80;
 
-    fill_dirt(&mut world);
-    carve_surface(&mut world, &mut rng);
-    fill_stone(&mut world, &mut rng);
-    carve_caves(&mut world, &mut rng);
+const CHEST_ATTEMPTS: usize = 200;
+const CHEST_DEPTH: i32 = 60;
 
-    commands.insert_resource(world);
+pub fn generate(mut commands: Commands, mut save_state: ResMut<SaveState>) {
+    if let Some((world, saved_player)) = save::load(&mut save_state) {
+        commands.insert_resource(world);
+        commands.insert_resource(saved_player);
+        commands.insert_resource(NextState(Some(GameState::InGame)));
+        return;
+    }
+
+    commands.insert_resource(generate_world(&mut thread_rng()));
     commands.insert_resource(NextState(Some(GameState::InGame)));
 }
 
+fn generate_world(rng: &mut impl Rng) -> WorldStorage {
+    let mut world = WorldStorage::new();
+
+    fill_dirt(&mut world);
+    carve_surface(&mut world, rng);
+    fill_stone(&mut world, rng);
+    carve_caves(&mut world, rng);
+    freeze_cave_floors(&mut world, rng);
+    place_chests(&mut world, rng);
+    world.compact();
+    world
+}
+
 fn fill_dirt(world: &mut WorldStorage) {
     for x in 0..WORLD_BLOCK_SIZE.x {
         for y in 0..WORLD_BLOCK_SIZE.y {
@@ -43,12 +65,14 @@ fn fill_dirt(world: &mut WorldStorage) {
     }
 }
 
-fn carve_surface(world: &mut WorldStorage, rng: &mut ThreadRng) {
+fn carve_surface(world: &mut WorldStorage, rng: &mut impl Rng) {
     let fbm = Fbm::<Perlin>::new(rng.gen());
 
     for x in 0..WORLD_BLOCK_SIZE.x {
         let val = (fbm.get([x as f64 / SURFACE_LENGTH, 0.0, 0.0]) * SURFACE_HEIGHT + WORLD_BLOCK_SIZE.y as f64 - SURFACE_OFFSET) as i32;
+        let growth_stage = rng.gen_range(0..=StateProperty::GrowthStage.max());
         world.set_block(ivec2(x, val), Block::Grass);
+        world.set_state(ivec2(x, val), BlockState::default().with(Block::Grass, StateProperty::GrowthStage, growth_stage));
         world.set_wall(ivec2(x, val), Block::Dirt);
 
         for y in (val + 1)..WORLD_BLOCK_SIZE.y {
@@ -58,7 +82,7 @@ fn carve_surface(world: &mut WorldStorage, rng: &mut ThreadRng) {
     }
 }
 
-fn fill_stone(world: &mut WorldStorage, rng: &mut ThreadRng) {
+fn fill_stone(world: &mut WorldStorage, rng: &mut impl Rng) {
     for x in 0..WORLD_BLOCK_SIZE.x {
         let val = ((x as f32 * STONE_LENGTH).sin() * STONE_HEIGHT + WORLD_BLOCK_SIZE.y as f32 - STONE_OFFSET) as usize;
 
@@ -79,7 +103,7 @@ fn fill_stone(world: &mut WorldStorage, rng: &mut ThreadRng) {
     }
 }
 
-fn carve_caves(world: &mut WorldStorage, rng: &mut ThreadRng) {
+fn carve_caves(world: &mut WorldStorage, rng: &mut impl Rng) {
     let fbm = Fbm::<Perlin>::new(rng.gen());
 
     for y in 0..WORLD_BLOCK_SIZE.y {
@@ -95,3 +119,65 @@ fn carve_caves(world: &mut WorldStorage, rng: &mut ThreadRng) {
         }
     }
 }
+
+/// patches of ice on deep cave floors
+fn freeze_cave_floors(world: &mut WorldStorage, rng: &mut impl Rng) {
+    let fbm = Fbm::<Perlin>::new(rng.gen());
+
+    for y in 0..WORLD_BLOCK_SIZE.y - ICE_DEPTH {
+        for x in 0..WORLD_BLOCK_SIZE.x {
+            if world.get_block(ivec2(x, y)).unwrap() != Block::Stone
+                || world.get_block(ivec2(x, y + 1)).unwrap() != Block::Air {
+                continue;
+            }
+
+            let val = fbm.get([x as f64 / ICE_SCALE, y as f64 / ICE_SCALE, 0.0]);
+            if val > ICE_THRESHOLD {
+                world.set_block(ivec2(x, y), Block::Ice);
+            }
+        }
+    }
+}
+
+fn place_chests(world: &mut WorldStorage, rng: &mut impl Rng) {
+    for _ in 0..CHEST_ATTEMPTS {
+        let x = rng.gen_range(0..WORLD_BLOCK_SIZE.x);
+        let y = rng.gen_range(1..WORLD_BLOCK_SIZE.y - CHEST_DEPTH);
+
+        // only on cave floors
+        if world.get_block(ivec2(x, y)).unwrap() != Block::Air
+            || world.get_block(ivec2(x, y - 1)).unwrap() == Block::Air {
+            continue;
+        }
+
+        let mut chest = Chest::new();
+        for slot in chest.slots.iter_mut().take(rng.gen_range(1..CHEST_SLOTS / 2)) {
+            let item = *[ItemId::Stone, ItemId::Dirt, ItemId::Pebble, ItemId::Mushroom].choose(rng).unwrap();
+            *slot = Some(ItemStack::new(item, rng.gen_range(1..=16)));
+        }
+        world.set_block(ivec2(x, y), Block::Chest);
+        world.set_tile_data(ivec2(x, y), Some(chest.encode()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::{chunks::CHUNK_SIZE, storage::WORLD_CHUNK_SIZE};
+
+    use super::*;
+
+    const SEED: u64 = 1;
+
+    #[test]
+    fn generated_world_is_a_fraction_of_flat_arrays() {
+        let world = generate_world(&mut StdRng::seed_from_u64(SEED));
+
+        // blocks, walls and flips as three plain arrays per chunk, how chunks used to be stored
+        let chunk_area = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+        let flat_chunk = chunk_area * (2 * std::mem::size_of::<Block>() + std::mem::size_of::<(bool, bool)>());
+        let flat = flat_chunk * (WORLD_CHUNK_SIZE.x * WORLD_CHUNK_SIZE.y) as usize;
+
+        let heap_size = world.heap_size();
+        assert!(heap_size * 4 < flat, "{} is more than a quarter of {}", heap_size, flat);
+    }
+}
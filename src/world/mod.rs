@@ -13,8 +13,13 @@ use self::{
 pub(crate) mod block;
 pub(crate) mod chunks;
 mod gen;
+mod palette;
 pub(crate) mod position;
+pub(crate) mod raycast;
+pub(crate) mod save;
+pub(crate) mod state;
 pub(crate) mod storage;
+pub(crate) mod tile_entity;
 
 #[allow(dead_code)]
 #[derive(AssetCollection, Resource)]
@@ -37,17 +42,33 @@ impl Plugin for WorldPlugin {
 
         app.add_collection_to_loading_state::<_, TileTextures>(GameState::AssetLoading);
         app.init_resource::<chunks::LoadedChunks>();
+        app.init_resource::<chunks::ChunkLoadRadius>();
+        app.init_resource::<save::SaveState>();
+        app.init_resource::<tile_entity::TileEntities>();
+        app.init_resource::<tile_entity::OpenChest>();
         app.insert_resource(Colls(HashMap::new()));
         app.add_event::<ReloadChunks>();
         app.add_event::<ReloadChunk>();
+        app.add_event::<chunks::RedrawTile>();
 
         app.add_systems(OnEnter(GameState::WorldGeneration), generate);
 
         app.add_systems(Update,
             (
                 chunks::spawn_chunks_near_player,
-                chunks::reload_chunk
+                chunks::reload_chunk,
+                tile_entity::sync_tile_entities,
+                tile_entity::persist_tile_entities,
+                tile_entity::update_pressure_plates,
+                chunks::redraw_tiles.after(tile_entity::update_pressure_plates),
+                tile_entity::open_chest,
+                tile_entity::chest_window,
+                save::autosave,
+                save::poll_save,
+                save::save_error_window,
             ).run_if(in_state(GameState::InGame)),
         );
+
+        app.add_systems(Update, save::save_on_exit);
     }
 }
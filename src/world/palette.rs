@@ -0,0 +1,295 @@
+use std::io;
+
+use super::save::{write_u32, write_u64, ByteReader};
+
+/// fixed length storage that keeps a palette of distinct values and bit-packed indices into it.
+/// chunks that only contain a single value (all air sky chunks, solid stone) store no indices at all
+pub struct PaletteStorage<T> {
+    len: usize,
+    palette: Vec<T>,
+    bits: u32,
+    data: Vec<u64>,
+}
+
+impl<T: Copy + Eq> PaletteStorage<T> {
+    pub fn new(len: usize, value: T) -> Self {
+        Self {
+            len,
+            palette: vec![value],
+            bits: 0,
+            data: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> T {
+        if self.bits == 0 {
+            return self.palette[0];
+        }
+        self.palette[self.get_index(index)]
+    }
+
+    pub fn set(&mut self, index: usize, value: T) {
+        let palette_index = match self.palette.iter().position(|v| *v == value) {
+            Some(i) => i,
+            None => {
+                self.palette.push(value);
+                let needed = bits_for(self.palette.len());
+                if needed > self.bits {
+                    self.repack(needed);
+                }
+                self.palette.len() - 1
+            }
+        };
+
+        if self.bits == 0 {
+            return;
+        }
+        self.set_index(index, palette_index);
+    }
+
+    /// drops palette entries that are no longer used and shrinks the indices,
+    /// falling back to the uniform representation when only one value is left
+    pub fn compact(&mut self) {
+        if self.bits == 0 {
+            return;
+        }
+
+        let mut used = vec![false; self.palette.len()];
+        for i in 0..self.len {
+            used[self.get_index(i)] = true;
+        }
+        if used.iter().all(|u| *u) && bits_for(self.palette.len()) == self.bits {
+            return;
+        }
+
+        let mut remap = vec![0; self.palette.len()];
+        let mut palette = Vec::new();
+        for (i, value) in self.palette.iter().enumerate() {
+            if used[i] {
+                remap[i] = palette.len();
+                palette.push(*value);
+            }
+        }
+
+        let indices: Vec<usize> = (0..self.len).map(|i| remap[self.get_index(i)]).collect();
+        self.palette = palette;
+        self.bits = bits_for(self.palette.len());
+        self.data = vec![0; words_for(self.len, self.bits)];
+        if self.bits == 0 {
+            return;
+        }
+        for (i, palette_index) in indices.into_iter().enumerate() {
+            self.set_index(i, palette_index);
+        }
+    }
+
+    /// bytes allocated on the heap by this storage
+    pub fn heap_size(&self) -> usize {
+        self.palette.capacity() * std::mem::size_of::<T>() + self.data.capacity() * 8
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>, to_raw: impl Fn(T) -> u32) {
+        write_u32(out, self.palette.len() as u32);
+        for value in self.palette.iter() {
+            write_u32(out, to_raw(*value));
+        }
+        for word in self.data.iter() {
+            write_u64(out, *word);
+        }
+    }
+
+    /// fails on values `from_raw` does not recognise
+    pub fn decode(len: usize, reader: &mut ByteReader, from_raw: impl Fn(u32) -> Option<T>) -> io::Result<Self> {
+        let palette_len = reader.read_u32()? as usize;
+        if palette_len == 0 || palette_len > len {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid palette length"));
+        }
+        let mut palette = Vec::with_capacity(palette_len);
+        for _ in 0..palette_len {
+            let value = from_raw(reader.read_u32()?)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unknown palette value"))?;
+            palette.push(value);
+        }
+        let bits = bits_for(palette_len);
+        let mut data = Vec::with_capacity(words_for(len, bits));
+        for _ in 0..words_for(len, bits) {
+            data.push(reader.read_u64()?);
+        }
+        let storage = Self { len, palette, bits, data };
+        if bits != 0 && (0..len).any(|i| storage.get_index(i) >= palette_len) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "palette index out of range"));
+        }
+        Ok(storage)
+    }
+
+    fn repack(&mut self, bits: u32) {
+        let indices: Vec<usize> = (0..self.len)
+            .map(|i| if self.bits == 0 { 0 } else { self.get_index(i) })
+            .collect();
+        self.bits = bits;
+        self.data = vec![0; words_for(self.len, bits)];
+        for (i, palette_index) in indices.into_iter().enumerate() {
+            self.set_index(i, palette_index);
+        }
+    }
+
+    fn get_index(&self, index: usize) -> usize {
+        let per_word = 64 / self.bits as usize;
+        let word = self.data[index / per_word];
+        let shift = (index % per_word) as u32 * self.bits;
+        ((word >> shift) & mask(self.bits)) as usize
+    }
+
+    fn set_index(&mut self, index: usize, palette_index: usize) {
+        let per_word = 64 / self.bits as usize;
+        let word = &mut self.data[index / per_word];
+        let shift = (index % per_word) as u32 * self.bits;
+        *word &= !(mask(self.bits) << shift);
+        *word |= (palette_index as u64 & mask(self.bits)) << shift;
+    }
+}
+
+/// bits per index, always a power of two so an index never straddles two words
+fn bits_for(palette_len: usize) -> u32 {
+    match palette_len {
+        0..=1 => 0,
+        2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        17..=256 => 8,
+        _ => 16,
+    }
+}
+
+fn words_for(len: usize, bits: u32) -> usize {
+    if bits == 0 {
+        return 0;
+    }
+    len.div_ceil(64 / bits as usize)
+}
+
+fn mask(bits: u32) -> u64 {
+    (1 << bits) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEN: usize = 1024;
+
+    /// storage where index `i` holds `i % distinct`
+    fn filled(distinct: u32) -> PaletteStorage<u32> {
+        let mut storage = PaletteStorage::new(LEN, 0);
+        for i in 0..LEN {
+            storage.set(i, i as u32 % distinct);
+        }
+        storage
+    }
+
+    #[test]
+    fn set_and_get_across_widths() {
+        for (distinct, bits) in [(1, 0), (2, 1), (3, 2), (4, 2), (5, 4), (16, 4), (17, 8), (256, 8), (257, 16)] {
+            let storage = filled(distinct);
+            assert_eq!(storage.bits, bits, "{} values", distinct);
+            assert_eq!(storage.data.len(), words_for(LEN, bits));
+            for i in 0..LEN {
+                assert_eq!(storage.get(i), i as u32 % distinct, "{} values at {}", distinct, i);
+            }
+        }
+    }
+
+    #[test]
+    fn growing_keeps_earlier_values() {
+        let mut storage = PaletteStorage::new(LEN, 7);
+        storage.set(3, 1);
+        assert_eq!(storage.bits, 1);
+        // pushes the palette to 17 entries, repacking through 2, 4 and 8 bits
+        for value in 2..=16 {
+            storage.set(100 + value as usize, value);
+        }
+        assert_eq!(storage.bits, 8);
+        assert_eq!(storage.get(0), 7);
+        assert_eq!(storage.get(3), 1);
+        for value in 2..=16 {
+            assert_eq!(storage.get(100 + value as usize), value);
+        }
+        assert_eq!(storage.get(LEN - 1), 7);
+    }
+
+    #[test]
+    fn compact_falls_back_to_uniform() {
+        let mut storage = filled(5);
+        for i in 0..LEN {
+            storage.set(i, 9);
+        }
+        assert_eq!(storage.bits, 4);
+
+        storage.compact();
+        assert_eq!(storage.bits, 0);
+        assert_eq!(storage.palette, vec![9]);
+        assert!(storage.data.is_empty());
+        assert_eq!(storage.get(LEN / 2), 9);
+    }
+
+    #[test]
+    fn compact_shrinks_to_used_values() {
+        let mut storage = filled(17);
+        for i in 0..LEN {
+            storage.set(i, i as u32 % 3);
+        }
+        storage.compact();
+        assert_eq!(storage.bits, 2);
+        assert_eq!(storage.palette.len(), 3);
+        for i in 0..LEN {
+            assert_eq!(storage.get(i), i as u32 % 3);
+        }
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        for distinct in [1, 2, 4, 16, 200] {
+            let storage = filled(distinct);
+            let mut bytes = Vec::new();
+            storage.encode(&mut bytes, |value| value);
+
+            let mut reader = ByteReader::new(&bytes);
+            let decoded = PaletteStorage::decode(LEN, &mut reader, Some).unwrap();
+            assert!(reader.take(1).is_err(), "trailing bytes");
+            assert_eq!(decoded.bits, storage.bits);
+            for i in 0..LEN {
+                assert_eq!(decoded.get(i), storage.get(i));
+            }
+        }
+    }
+
+    #[test]
+    fn decode_rejects_bad_data() {
+        let mut bytes = Vec::new();
+        write_u32(&mut bytes, 0);
+        assert!(PaletteStorage::decode(LEN, &mut ByteReader::new(&bytes), Some).is_err());
+
+        // three palette entries, one index pointing past them
+        let mut bytes = Vec::new();
+        write_u32(&mut bytes, 3);
+        for value in 0..3 {
+            write_u32(&mut bytes, value);
+        }
+        write_u64(&mut bytes, 3);
+        for _ in 1..words_for(LEN, 2) {
+            write_u64(&mut bytes, 0);
+        }
+        assert!(PaletteStorage::decode(LEN, &mut ByteReader::new(&bytes), Some).is_err());
+
+        // a value the caller does not know
+        let mut bytes = Vec::new();
+        filled(2).encode(&mut bytes, |value| value);
+        assert!(PaletteStorage::decode(LEN, &mut ByteReader::new(&bytes), |raw| (raw == 0).then_some(raw)).is_err());
+
+        // truncated
+        let mut bytes = Vec::new();
+        filled(4).encode(&mut bytes, |value| value);
+        bytes.truncate(bytes.len() - 1);
+        assert!(PaletteStorage::decode(LEN, &mut ByteReader::new(&bytes), Some).is_err());
+    }
+}
@@ -1,6 +1,9 @@
 use bevy::prelude::*;
 
-use super::{chunks::CHUNK_SIZE, storage::WORLD_CHUNK_SIZE};
+use super::{chunks::{BLOCK_SIZE, CHUNK_SIZE}, storage::WORLD_CHUNK_SIZE};
+
+/// keeps boxes from counting tiles they only touch as overlapping
+pub const EPSILON: f32 = 0.001;
 
 #[derive(Component, Eq, Hash, Copy, Clone)]
 pub struct ChunkPos(pub IVec2);
@@ -15,8 +18,8 @@ impl ChunkPos {
 
     pub fn from_block_pos(block_pos: IVec2) -> Self {
         Self(IVec2 {
-            x: block_pos.x / CHUNK_SIZE,
-            y: block_pos.y / CHUNK_SIZE,
+            x: block_pos.x.div_euclid(CHUNK_SIZE),
+            y: block_pos.y.div_euclid(CHUNK_SIZE),
         })
     }
 }
@@ -31,3 +34,13 @@ impl PartialEq for ChunkPos {
 pub fn linearize(block_pos: IVec2) -> usize {
     (block_pos.x + CHUNK_SIZE * block_pos.y) as usize
 }
+
+/// tile containing a world position, tiles are centered on multiples of `BLOCK_SIZE`
+pub fn tile_pos(position: Vec2) -> IVec2 {
+    IVec2::new(tile(position.x), tile(position.y))
+}
+
+/// one coordinate of `tile_pos`
+pub fn tile(value: f32) -> i32 {
+    (value / BLOCK_SIZE as f32 + 0.5).floor() as i32
+}
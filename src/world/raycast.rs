@@ -0,0 +1,249 @@
+use bevy::{math::vec2, prelude::*};
+
+use crate::physics::PhysicsMaterial;
+
+use super::{
+    block::{Block, BlockShape},
+    chunks::{BLOCK_SIZE, CHUNK_SIZE},
+    position::{tile_pos, ChunkPos, EPSILON},
+    storage::{WorldStorage, WORLD_BLOCK_SIZE},
+};
+
+/// first tile a ray ran into
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    pub block_pos: IVec2,
+    /// normal of the face the ray entered through, zero when it started inside the tile
+    pub normal: IVec2,
+    /// from the ray origin to the hit, in pixels
+    pub distance: f32,
+}
+
+impl WorldStorage {
+    /// whether bodies collide with the block, positions outside the world are never solid
+    pub fn is_solid(&self, block_pos: IVec2) -> bool {
+        self.block_at(block_pos).is_some_and(|block| block.is_solid())
+    }
+
+    /// whether there is any block at all, including ones that can be walked through
+    pub fn is_occupied(&self, block_pos: IVec2) -> bool {
+        self.block_at(block_pos).is_some_and(|block| block != Block::Air)
+    }
+
+    /// collision shape of the tile, positions outside the world are empty
+    pub fn shape(&self, block_pos: IVec2) -> BlockShape {
+        let Some(chunk_data) = self.get_chunk_data(ChunkPos::from_block_pos(block_pos)) else {
+            return BlockShape::Empty;
+        };
+        let block_rel_pos = IVec2::new(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
+        match (chunk_data.get_block(block_rel_pos), chunk_data.get_state(block_rel_pos)) {
+            (Some(block), Some(state)) => block.shape(state),
+            _ => BlockShape::Empty,
+        }
+    }
+
+    /// material of the block at the position, air outside the world
+    pub fn material(&self, block_pos: IVec2) -> PhysicsMaterial {
+        self.block_at(block_pos).unwrap_or(Block::Air).material()
+    }
+
+    fn block_at(&self, block_pos: IVec2) -> Option<Block> {
+        let chunk_data = self.get_chunk_data(ChunkPos::from_block_pos(block_pos))?;
+        let block_rel_pos = IVec2::new(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
+        chunk_data.get_block(block_rel_pos)
+    }
+
+    /// walks the tiles between two points in block space (block centers at whole numbers)
+    /// and returns the first occupied one, not counting the tile the ray starts in
+    pub fn raycast(&self, from: Vec2, to: Vec2) -> Option<IVec2> {
+        let start = from + 0.5;
+        let start_pos = start.floor().as_ivec2();
+        walk(start, to - from, 1.0, |block_pos| block_pos != start_pos && self.is_occupied(block_pos))
+            .map(|hit| hit.block_pos)
+    }
+
+    /// casts a ray in world space against solid tiles, `max_distance` is in pixels.
+    /// a ray starting inside a solid tile hits it right away with a zero normal
+    pub fn cast_ray(&self, origin: Vec2, direction: Vec2, max_distance: f32) -> Option<RayHit> {
+        let direction = direction.normalize_or_zero();
+        if direction == Vec2::ZERO { return None; };
+
+        // past the farthest world corner the ray is outside the world, where nothing is solid,
+        // so even an unbounded ray ends
+        let block_size = BLOCK_SIZE as f32;
+        let world_min = Vec2::splat(-block_size / 2.0);
+        let world_max = (WORLD_BLOCK_SIZE * BLOCK_SIZE).as_vec2() - block_size / 2.0;
+        let farthest = [world_min, world_max, vec2(world_min.x, world_max.y), vec2(world_max.x, world_min.y)]
+            .iter()
+            .map(|corner| corner.distance(origin))
+            .fold(0.0, f32::max);
+        let max_distance = max_distance.min(farthest);
+
+        let hit = walk(origin / block_size + 0.5, direction, max_distance / block_size, |block_pos| self.is_solid(block_pos))?;
+        Some(RayHit { distance: hit.distance * block_size, ..hit })
+    }
+
+    /// solid tiles overlapping a box in world space, touching edges do not count
+    pub fn overlap_box(&self, center: Vec2, half_size: Vec2) -> Vec<IVec2> {
+        let min = tile_pos(center - half_size + EPSILON);
+        let max = tile_pos(center + half_size - EPSILON);
+
+        let mut tiles = Vec::new();
+        for y in min.y..=max.y {
+            for x in min.x..=max.x {
+                let block_pos = IVec2::new(x, y);
+                if self.is_solid(block_pos) {
+                    tiles.push(block_pos);
+                }
+            }
+        }
+        tiles
+    }
+
+    /// solid tiles overlapping a circle in world space
+    pub fn overlap_circle(&self, center: Vec2, radius: f32) -> Vec<IVec2> {
+        let half_block = Vec2::splat(BLOCK_SIZE as f32 / 2.0);
+        let mut tiles = self.overlap_box(center, Vec2::splat(radius));
+        tiles.retain(|block_pos| {
+            let tile_center = block_pos.as_vec2() * BLOCK_SIZE as f32;
+            let closest = center.clamp(tile_center - half_block, tile_center + half_block);
+            closest.distance_squared(center) < radius * radius
+        });
+        tiles
+    }
+}
+
+/// DDA through the grid starting at `start`, where tile borders are at whole numbers.
+/// `max_t` is how far to go in multiples of `dir`, the hit distance uses the same unit
+fn walk(start: Vec2, dir: Vec2, max_t: f32, hits: impl Fn(IVec2) -> bool) -> Option<RayHit> {
+    let mut block_pos = start.floor().as_ivec2();
+    if hits(block_pos) {
+        return Some(RayHit { block_pos, normal: IVec2::ZERO, distance: 0.0 });
+    }
+
+    let step = IVec2::new(dir.x.signum() as i32, dir.y.signum() as i32);
+
+    // distance along the ray to cross one tile, and to the next tile border
+    let t_delta = Vec2::new(1.0 / dir.x.abs(), 1.0 / dir.y.abs());
+    let mut t_max = Vec2::new(first_crossing(start.x, dir.x), first_crossing(start.y, dir.y));
+
+    loop {
+        let (t, normal) = if t_max.x < t_max.y {
+            let t = t_max.x;
+            block_pos.x += step.x;
+            t_max.x += t_delta.x;
+            (t, IVec2::new(-step.x, 0))
+        } else {
+            let t = t_max.y;
+            block_pos.y += step.y;
+            t_max.y += t_delta.y;
+            (t, IVec2::new(0, -step.y))
+        };
+        if t > max_t { return None; };
+
+        if hits(block_pos) {
+            return Some(RayHit { block_pos, normal, distance: t });
+        }
+    }
+}
+
+/// ray distance until a ray starting at `start` with direction `dir` crosses the next whole number
+fn first_crossing(start: f32, dir: f32) -> f32 {
+    if dir > 0.0 {
+        (start.floor() + 1.0 - start) / dir
+    } else if dir < 0.0 {
+        (start - start.floor()) / -dir
+    } else {
+        f32::INFINITY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{ivec2, vec2};
+
+    use super::*;
+
+    /// empty world with a stone tile at each of `solid`
+    fn world_with(solid: &[IVec2]) -> WorldStorage {
+        let mut world = WorldStorage::new();
+        for block_pos in solid {
+            world.set_block(*block_pos, Block::Stone);
+        }
+        world
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.001, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn ray_hits_along_axis() {
+        let world = world_with(&[ivec2(10, 5)]);
+        let hit = world.cast_ray(vec2(40.0, 40.0), Vec2::X, 100.0).unwrap();
+        assert_eq!(hit.block_pos, ivec2(10, 5));
+        assert_eq!(hit.normal, ivec2(-1, 0));
+        // left edge of tile 10 is at 76
+        assert_close(hit.distance, 36.0);
+
+        let hit = world.cast_ray(vec2(80.0, 0.0), Vec2::Y, 100.0).unwrap();
+        assert_eq!(hit.block_pos, ivec2(10, 5));
+        assert_eq!(hit.normal, ivec2(0, -1));
+        assert_close(hit.distance, 36.0);
+    }
+
+    #[test]
+    fn ray_hits_on_diagonal() {
+        let row: Vec<IVec2> = (0..20).map(|x| ivec2(x, 9)).collect();
+        let world = world_with(&row);
+        let hit = world.cast_ray(vec2(42.0, 40.0), vec2(1.0, 1.0), 100.0).unwrap();
+        // bottom edge of row 9 is at 68, reached at x = 70
+        assert_eq!(hit.block_pos, ivec2(9, 9));
+        assert_eq!(hit.normal, ivec2(0, -1));
+        assert_close(hit.distance, 28.0 * 2f32.sqrt());
+    }
+
+    #[test]
+    fn ray_starting_inside_solid_hits_at_once() {
+        let world = world_with(&[ivec2(10, 5)]);
+        let hit = world.cast_ray(vec2(81.0, 39.0), Vec2::NEG_X, 100.0).unwrap();
+        assert_eq!(hit.block_pos, ivec2(10, 5));
+        assert_eq!(hit.normal, IVec2::ZERO);
+        assert_close(hit.distance, 0.0);
+    }
+
+    #[test]
+    fn ray_misses_beyond_max_distance() {
+        let world = world_with(&[ivec2(10, 5)]);
+        assert_eq!(world.cast_ray(vec2(40.0, 40.0), Vec2::X, 35.0), None);
+        assert_eq!(world.cast_ray(vec2(40.0, 40.0), Vec2::NEG_X, 100.0), None);
+        assert_eq!(world.cast_ray(vec2(40.0, 40.0), Vec2::ZERO, 100.0), None);
+    }
+
+    #[test]
+    fn unbounded_ray_leaving_the_world_ends() {
+        let world = world_with(&[]);
+        assert_eq!(world.cast_ray(vec2(40.0, 40.0), vec2(-1.0, 0.3), f32::INFINITY), None);
+        assert_eq!(world.cast_ray(vec2(-100.0, -100.0), Vec2::NEG_Y, f32::INFINITY), None);
+    }
+
+    #[test]
+    fn overlap_box_ignores_touching_edges() {
+        let world = world_with(&[ivec2(10, 5)]);
+        // tile 10 spans x 76..84 and y 36..44
+        assert!(world.overlap_box(vec2(72.0, 40.0), vec2(4.0, 4.0)).is_empty());
+        assert!(world.overlap_box(vec2(80.0, 48.0), vec2(4.0, 4.0)).is_empty());
+        assert_eq!(world.overlap_box(vec2(72.5, 40.0), vec2(4.0, 4.0)), vec![ivec2(10, 5)]);
+    }
+
+    #[test]
+    fn overlap_circle_ignores_touching_edges_and_corners() {
+        let world = world_with(&[ivec2(10, 5)]);
+        assert!(world.overlap_circle(vec2(72.0, 40.0), 4.0).is_empty());
+        assert_eq!(world.overlap_circle(vec2(72.0, 40.0), 4.5), vec![ivec2(10, 5)]);
+
+        // the corner at (76, 36) is sqrt(32) away, inside the bounding box but outside the circle
+        assert!(world.overlap_circle(vec2(72.0, 32.0), 5.6).is_empty());
+        assert_eq!(world.overlap_circle(vec2(72.0, 32.0), 5.7), vec![ivec2(10, 5)]);
+    }
+}
@@ -0,0 +1,669 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    thread::{self, JoinHandle},
+};
+
+use bevy::{
+    app::AppExit, math::ivec2, prelude::*, utils::HashMap, window::WindowCloseRequested,
+};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    controls::Action,
+    items::{ItemId, ItemStack},
+    player::{inventory::Inventory, player::Player},
+};
+
+use super::{
+    block::Block,
+    chunks::CHUNK_SIZE,
+    position::ChunkPos,
+    storage::{ChunkData, WorldStorage},
+};
+
+const SAVE_DIR: &str = "./saves";
+const SAVE_PATH: &str = "./saves/world.sav";
+const BACKUP_COUNT: usize = 3;
+const AUTOSAVE_INTERVAL: f32 = 60.0;
+
+const MAGIC: &[u8; 4] = b"ASTS";
+const VERSION: u32 = 5;
+
+// the oldest save still read and the first version with each addition, older saves are
+// read with defaults for whatever they lack
+const OLDEST_VERSION: u32 = 1;
+/// block state and tile data in chunks
+const VERSION_BLOCK_STATE: u32 = 2;
+/// player inventory, stacks still hold the block they place
+const VERSION_INVENTORY: u32 = 3;
+/// stacks hold an item id instead of the block they place
+const VERSION_ITEM_IDS: u32 = 4;
+/// stacks have wear
+const VERSION_WEAR: u32 = 5;
+
+/// player state restored from a save, read by `spawn_player`
+#[derive(Resource)]
+pub struct SavedPlayer {
+    pub position: Vec2,
+    pub inventory: Vec<Option<ItemStack>>,
+}
+
+/// a save file that exists but could not be read
+struct LoadFailure {
+    path: String,
+    error: String,
+    /// where the file was moved so saving cannot overwrite it, `None` if moving it failed
+    moved_to: Option<String>,
+}
+
+#[derive(Resource)]
+pub struct SaveState {
+    timer: Timer,
+    chunk_cache: HashMap<ChunkPos, Vec<u8>>,
+    task: Option<JoinHandle<io::Result<()>>>,
+    error: Option<String>,
+    /// shown until dismissed
+    load_failures: Vec<LoadFailure>,
+}
+
+impl Default for SaveState {
+    fn default() -> Self {
+        Self {
+            timer: Timer::from_seconds(AUTOSAVE_INTERVAL, TimerMode::Repeating),
+            chunk_cache: HashMap::new(),
+            task: None,
+            error: None,
+            load_failures: Vec::new(),
+        }
+    }
+}
+
+impl SaveState {
+    /// re-encodes dirty chunks into the cache and builds the whole save file from it
+    fn build(&mut self, world_storage: &mut WorldStorage, player_position: Vec2, inventory: &Inventory) -> Vec<u8> {
+        for chunk_pos in world_storage.take_dirty() {
+            let Some(chunk_data) = world_storage.get_chunk_data(chunk_pos) else { continue; };
+            let mut bytes = Vec::new();
+            chunk_data.encode(&mut bytes);
+            self.chunk_cache.insert(chunk_pos, bytes);
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        write_u32(&mut out, VERSION);
+        write_f32(&mut out, player_position.x);
+        write_f32(&mut out, player_position.y);
+        write_slots(&mut out, &inventory.slots);
+        write_u32(&mut out, self.chunk_cache.len() as u32);
+        for (chunk_pos, bytes) in self.chunk_cache.iter() {
+            write_u32(&mut out, chunk_pos.0.x as u32);
+            write_u32(&mut out, chunk_pos.0.y as u32);
+            write_u32(&mut out, bytes.len() as u32);
+            out.extend_from_slice(bytes);
+        }
+        out
+    }
+
+    /// an unreadable save is still where saving would write, until the player agrees to overwrite it
+    fn overwrite_blocked(&self) -> bool {
+        self.load_failures.iter().any(|failure| failure.moved_to.is_none())
+    }
+
+    /// blocks until the in-flight background save (if any) is done
+    fn finish_task(&mut self) {
+        let Some(task) = self.task.take() else { return; };
+        self.handle_result(task.join());
+    }
+
+    fn handle_result(&mut self, result: thread::Result<io::Result<()>>) {
+        match result {
+            Ok(Ok(())) => {
+                info!("saved world to {}", SAVE_PATH);
+                self.error = None;
+            }
+            Ok(Err(err)) => {
+                error!("could not save world: {}", err);
+                self.error = Some(err.to_string());
+            }
+            Err(_) => {
+                error!("could not save world: save thread panicked");
+                self.error = Some("save thread panicked".to_string());
+            }
+        }
+    }
+}
+
+pub fn autosave(
+    mut save_state: ResMut<SaveState>,
+    mut world_storage: ResMut<WorldStorage>,
+    player_query: Query<(&Transform, &Inventory), With<Player>>,
+    time: Res<Time>,
+) {
+    if !save_state.timer.tick(time.delta()).just_finished() { return; };
+    if save_state.task.is_some() || save_state.overwrite_blocked() { return; };
+    let Ok((player_transform, inventory)) = player_query.get_single() else { return; };
+
+    let bytes = save_state.build(&mut world_storage, player_transform.translation.truncate(), inventory);
+    save_state.task = Some(thread::spawn(move || write_save(&bytes)));
+}
+
+pub fn poll_save(mut save_state: ResMut<SaveState>) {
+    if !save_state.task.as_ref().is_some_and(|task| task.is_finished()) { return; };
+    save_state.finish_task();
+}
+
+/// replaces `bevy::window::close_on_esc`, the window only closes once the world is safely on disk
+pub fn save_on_exit(
+    mut save_state: ResMut<SaveState>,
+    world_storage: Option<ResMut<WorldStorage>>,
+    player_query: Query<(&Transform, &Inventory), With<Player>>,
+    actions: Res<Input<Action>>,
+    mut close_events: EventReader<WindowCloseRequested>,
+    mut exit_event: EventWriter<AppExit>,
+) {
+    let close_requested = close_events.iter().count() > 0;
+    if !close_requested && !actions.just_pressed(Action::Quit) { return; };
+
+    let (Some(mut world_storage), Ok((player_transform, inventory))) = (world_storage, player_query.get_single()) else {
+        exit_event.send(AppExit);
+        return;
+    };
+    // the load failure window asks first
+    if save_state.overwrite_blocked() { return; };
+
+    save_state.finish_task();
+    let bytes = save_state.build(&mut world_storage, player_transform.translation.truncate(), inventory);
+    save_state.handle_result(Ok(write_save(&bytes)));
+
+    if save_state.error.is_none() {
+        exit_event.send(AppExit);
+    }
+}
+
+pub fn save_error_window(
+    mut contexts: EguiContexts,
+    mut save_state: ResMut<SaveState>,
+    mut exit_event: EventWriter<AppExit>,
+) {
+    if !save_state.load_failures.is_empty() {
+        let blocked = save_state.overwrite_blocked();
+        let mut dismissed = false;
+        egui::Window::new("load failed").show(contexts.ctx_mut(), |ui| {
+            for failure in save_state.load_failures.iter() {
+                ui.label(format!("could not load {}: {}", failure.path, failure.error));
+                match &failure.moved_to {
+                    Some(moved_to) => ui.label(format!("it was moved to {}", moved_to)),
+                    None => ui.label("it could not be moved aside, saving would overwrite it"),
+                };
+            }
+            if blocked {
+                ui.label("saving is paused until you decide");
+                if ui.button("overwrite it").clicked() {
+                    dismissed = true;
+                }
+                if ui.button("quit without saving").clicked() {
+                    exit_event.send(AppExit);
+                }
+            } else if ui.button("ok").clicked() {
+                dismissed = true;
+            }
+        });
+        if dismissed {
+            save_state.load_failures.clear();
+        }
+    }
+
+    let Some(error) = &save_state.error else { return; };
+
+    egui::Window::new("save failed").show(contexts.ctx_mut(), |ui| {
+        ui.label(format!("could not save world: {}", error));
+        ui.label("press esc to try again");
+        if ui.button("quit without saving").clicked() {
+            exit_event.send(AppExit);
+        }
+    });
+}
+
+/// reads the newest readable save, falling back to backups if it is corrupt.
+/// unreadable saves are moved aside so later saves and backup rotation cannot replace them
+pub fn load(save_state: &mut SaveState) -> Option<(WorldStorage, SavedPlayer)> {
+    let paths = std::iter::once(SAVE_PATH.to_string()).chain((1..=BACKUP_COUNT).map(backup_path));
+
+    for path in paths {
+        if !Path::new(&path).exists() { continue; };
+        match fs::read(&path).and_then(|bytes| decode_save(&bytes, save_state)) {
+            Ok(save) => {
+                info!("loaded world from {}", path);
+                return Some(save);
+            }
+            Err(err) => {
+                error!("could not load save {}: {}", path, err);
+                save_state.chunk_cache.clear();
+
+                let corrupt_path = corrupt_path(&path);
+                let moved_to = match fs::rename(&path, &corrupt_path) {
+                    Ok(()) => Some(corrupt_path),
+                    Err(err) => {
+                        error!("could not move {} aside: {}", path, err);
+                        None
+                    }
+                };
+                save_state.load_failures.push(LoadFailure { path, error: err.to_string(), moved_to });
+            }
+        }
+    }
+    None
+}
+
+fn decode_save(bytes: &[u8], save_state: &mut SaveState) -> io::Result<(WorldStorage, SavedPlayer)> {
+    let mut reader = ByteReader::new(bytes);
+    if reader.take(4)? != MAGIC {
+        return Err(invalid_data("not a world save"));
+    }
+    let version = reader.read_u32()?;
+    if version < OLDEST_VERSION || version > VERSION {
+        return Err(invalid_data(&format!("unsupported save version {}", version)));
+    }
+
+    let position = Vec2::new(reader.read_f32()?, reader.read_f32()?);
+    let inventory = if version >= VERSION_INVENTORY { read_versioned_slots(&mut reader, version)? } else { Vec::new() };
+    let mut world_storage = WorldStorage::new();
+    let chunk_count = reader.read_u32()?;
+    for _ in 0..chunk_count {
+        let chunk_pos = ChunkPos(ivec2(reader.read_u32()? as i32, reader.read_u32()? as i32));
+        let len = reader.read_u32()? as usize;
+        let chunk_bytes = reader.take(len)?;
+        if !chunk_pos.in_bounds() {
+            return Err(invalid_data("chunk out of bounds"));
+        }
+
+        let mut chunk_data = ChunkData::decode(&mut ByteReader::new(chunk_bytes), version >= VERSION_BLOCK_STATE)?;
+        if version == VERSION {
+            save_state.chunk_cache.insert(chunk_pos, chunk_bytes.to_vec());
+        } else {
+            migrate_tile_data(&mut chunk_data, version)?;
+            // cached in the current format so the next save does not write old chunks back
+            let mut bytes = Vec::new();
+            chunk_data.encode(&mut bytes);
+            save_state.chunk_cache.insert(chunk_pos, bytes);
+        }
+        world_storage.insert_chunk_data(chunk_pos, chunk_data);
+    }
+
+    Ok((world_storage, SavedPlayer { position, inventory }))
+}
+
+/// brings tile entity data written by an older version up to date, chests hold slots
+/// in the format of the save they are in
+fn migrate_tile_data(chunk_data: &mut ChunkData, version: u32) -> io::Result<()> {
+    for y in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            if chunk_data.get_block(ivec2(x, y)) != Some(Block::Chest) { continue; };
+            let Some(data) = chunk_data.get_tile_data(ivec2(x, y)) else { continue; };
+
+            let slots = read_versioned_slots(&mut ByteReader::new(data), version)?;
+            let mut out = Vec::new();
+            write_slots(&mut out, &slots);
+            chunk_data.set_tile_data(ivec2(x, y), Some(out));
+        }
+    }
+    Ok(())
+}
+
+/// writes to a temp file and renames it over the save, so a crash mid-write never leaves a broken save behind
+fn write_save(bytes: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(SAVE_DIR)?;
+    let temp_path = format!("{}.tmp", SAVE_PATH);
+    let mut file = File::create(&temp_path)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+
+    rotate_backups()?;
+    fs::rename(&temp_path, SAVE_PATH)
+}
+
+fn rotate_backups() -> io::Result<()> {
+    if !Path::new(SAVE_PATH).exists() {
+        return Ok(());
+    }
+    for i in (1..BACKUP_COUNT).rev() {
+        let path = backup_path(i);
+        if Path::new(&path).exists() {
+            fs::rename(&path, backup_path(i + 1))?;
+        }
+    }
+    // copy instead of rename so there is always a save on disk
+    fs::copy(SAVE_PATH, backup_path(1))?;
+    Ok(())
+}
+
+/// where an unreadable save is kept, never one that already holds an earlier unreadable save
+fn corrupt_path(path: &str) -> String {
+    let timestamp = chrono::Local::now().format("%Y-%m-%d_%H-%M-%S");
+    let mut corrupt_path = format!("{}.{}.corrupt", path, timestamp);
+    let mut i = 1;
+    while Path::new(&corrupt_path).exists() {
+        corrupt_path = format!("{}.{}-{}.corrupt", path, timestamp, i);
+        i += 1;
+    }
+    corrupt_path
+}
+
+fn backup_path(i: usize) -> String {
+    format!("{}.{}", SAVE_PATH, i)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_f32(out: &mut Vec<u8>, value: f32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_slots(out: &mut Vec<u8>, slots: &[Option<ItemStack>]) {
+    write_u32(out, slots.len() as u32);
+    for slot in slots.iter() {
+        match slot {
+            Some(stack) => {
+                write_u32(out, stack.item as u32);
+                write_u32(out, stack.count);
+                write_u32(out, stack.wear);
+            }
+            None => {
+                write_u32(out, u32::MAX);
+                write_u32(out, 0);
+                write_u32(out, 0);
+            }
+        }
+    }
+}
+
+pub fn read_slots(reader: &mut ByteReader) -> io::Result<Vec<Option<ItemStack>>> {
+    read_versioned_slots(reader, VERSION)
+}
+
+fn read_versioned_slots(reader: &mut ByteReader, version: u32) -> io::Result<Vec<Option<ItemStack>>> {
+    let len = reader.read_u32()? as usize;
+    let mut slots = Vec::with_capacity(len.min(256));
+    for _ in 0..len {
+        let id = reader.read_u32()?;
+        let count = reader.read_u32()?;
+        let wear = if version >= VERSION_WEAR { reader.read_u32()? } else { 0 };
+        if id == u32::MAX {
+            slots.push(None);
+            continue;
+        }
+
+        let item = if version >= VERSION_ITEM_IDS {
+            Some(ItemId::from(id).ok_or_else(|| invalid_data("unknown item"))?)
+        } else {
+            // the block the stack would place, turned into the item mining it drops
+            Block::from(id).ok_or_else(|| invalid_data("unknown block"))?.drop_item()
+        };
+        slots.push(item.map(|item| ItemStack { item, count, wear }));
+    }
+    Ok(slots)
+}
+
+pub struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() - self.pos < len {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "save ended early"));
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    pub fn read_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::tile_entity::Chest;
+
+    use super::*;
+
+    const CHEST_POS: IVec2 = ivec2(5, 6);
+
+    /// a chunk with a chest at `CHEST_POS` holding `chest_slots`, and its bytes as a
+    /// save of `version` would have them
+    fn chunk_bytes(version: u32, chest_slots: &[u8]) -> Vec<u8> {
+        let mut chunk_data = ChunkData::new();
+        chunk_data.set_block(ivec2(1, 2), Block::Stone);
+        if version >= VERSION_BLOCK_STATE {
+            chunk_data.set_block(CHEST_POS, Block::Chest);
+            chunk_data.set_tile_data(CHEST_POS, Some(chest_slots.to_vec()));
+        }
+        let mut bytes = Vec::new();
+        chunk_data.encode(&mut bytes);
+        if version < VERSION_BLOCK_STATE {
+            // the uniform state palette (length and value) and the empty tile data count
+            bytes.truncate(bytes.len() - 12);
+        }
+        bytes
+    }
+
+    /// slots in the format of `version`, `id` is a block before item ids existed
+    fn slot_bytes(version: u32, slots: &[Option<(u32, u32)>]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_u32(&mut out, slots.len() as u32);
+        for slot in slots {
+            let (id, count) = slot.unwrap_or((u32::MAX, 0));
+            write_u32(&mut out, id);
+            write_u32(&mut out, count);
+            if version >= VERSION_WEAR {
+                write_u32(&mut out, 7);
+            }
+        }
+        out
+    }
+
+    fn save_bytes(version: u32, inventory: &[Option<(u32, u32)>], chest: &[Option<(u32, u32)>]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        write_u32(&mut out, version);
+        write_f32(&mut out, 12.0);
+        write_f32(&mut out, 34.0);
+        if version >= VERSION_INVENTORY {
+            out.extend(slot_bytes(version, inventory));
+        }
+        let chunk = chunk_bytes(version, &slot_bytes(version, chest));
+        write_u32(&mut out, 1);
+        write_u32(&mut out, 2);
+        write_u32(&mut out, 3);
+        write_u32(&mut out, chunk.len() as u32);
+        out.extend(chunk);
+        out
+    }
+
+    fn chest_pos() -> IVec2 {
+        ivec2(2, 3) * CHUNK_SIZE + CHEST_POS
+    }
+
+    #[test]
+    fn current_save_round_trips() {
+        let mut world_storage = WorldStorage::new();
+        world_storage.set_block(ivec2(70, 100), Block::Stone);
+        let mut chest = Chest::new();
+        chest.slots[1] = Some(ItemStack { item: ItemId::StonePickaxe, count: 1, wear: 3 });
+        world_storage.set_block(chest_pos(), Block::Chest);
+        world_storage.set_tile_data(chest_pos(), Some(chest.encode()));
+        let mut inventory = Inventory::new();
+        inventory.slots[0] = Some(ItemStack { item: ItemId::Bow, count: 1, wear: 9 });
+
+        let bytes = SaveState::default().build(&mut world_storage, Vec2::new(1.0, 2.0), &inventory);
+        let (mut loaded, player) = decode_save(&bytes, &mut SaveState::default()).unwrap();
+
+        assert_eq!(player.position, Vec2::new(1.0, 2.0));
+        assert_eq!(player.inventory, inventory.slots);
+        assert_eq!(loaded.get_block(ivec2(70, 100)), Some(Block::Stone));
+        let loaded_chest = Chest::decode(loaded.get_tile_data(chest_pos()).unwrap()).unwrap();
+        assert_eq!(loaded_chest.slots, chest.slots);
+    }
+
+    #[test]
+    fn reads_saves_without_block_state() {
+        let bytes = save_bytes(1, &[], &[]);
+        let (mut loaded, player) = decode_save(&bytes, &mut SaveState::default()).unwrap();
+
+        assert_eq!(player.position, Vec2::new(12.0, 34.0));
+        assert!(player.inventory.is_empty());
+        assert_eq!(loaded.get_block(ivec2(2, 3) * CHUNK_SIZE + ivec2(1, 2)), Some(Block::Stone));
+        assert_eq!(loaded.get_state(ivec2(2, 3) * CHUNK_SIZE + ivec2(1, 2)), Some(Default::default()));
+    }
+
+    #[test]
+    fn reads_saves_without_an_inventory() {
+        let bytes = save_bytes(2, &[], &[Some((3, 4))]);
+        let (loaded, player) = decode_save(&bytes, &mut SaveState::default()).unwrap();
+
+        assert_eq!(player.position, Vec2::new(12.0, 34.0));
+        assert!(player.inventory.is_empty());
+        let chest = Chest::decode(loaded.get_tile_data(chest_pos()).unwrap()).unwrap();
+        assert_eq!(chest.slots[0], Some(ItemStack::new(ItemId::Stone, 4)));
+    }
+
+    #[test]
+    fn reads_stacks_of_blocks_as_items() {
+        // dirt and grass blocks both become dirt, chests stay chests, border has no item
+        let slots = [Some((2, 5)), None, Some((1, 3)), Some((5, 1)), Some((4, 1))];
+        let bytes = save_bytes(3, &slots, &slots);
+        let (loaded, player) = decode_save(&bytes, &mut SaveState::default()).unwrap();
+
+        let expected = vec![
+            Some(ItemStack::new(ItemId::Dirt, 5)),
+            None,
+            Some(ItemStack::new(ItemId::Dirt, 3)),
+            Some(ItemStack::new(ItemId::Chest, 1)),
+            None,
+        ];
+        assert_eq!(player.inventory, expected);
+        let chest = Chest::decode(loaded.get_tile_data(chest_pos()).unwrap()).unwrap();
+        assert_eq!(chest.slots[..expected.len()], expected[..]);
+    }
+
+    #[test]
+    fn rejects_stacks_of_unknown_blocks() {
+        let bytes = save_bytes(3, &[Some((99, 1))], &[]);
+        assert!(decode_save(&bytes, &mut SaveState::default()).is_err());
+    }
+
+    #[test]
+    fn reads_stacks_without_wear() {
+        let slots = [Some((ItemId::StonePickaxe as u32, 1)), Some((ItemId::Pebble as u32, 20))];
+        let bytes = save_bytes(4, &slots, &slots);
+        let (loaded, player) = decode_save(&bytes, &mut SaveState::default()).unwrap();
+
+        let expected = vec![
+            Some(ItemStack::new(ItemId::StonePickaxe, 1)),
+            Some(ItemStack::new(ItemId::Pebble, 20)),
+        ];
+        assert_eq!(player.inventory, expected);
+        let chest = Chest::decode(loaded.get_tile_data(chest_pos()).unwrap()).unwrap();
+        assert_eq!(chest.slots[..expected.len()], expected[..]);
+    }
+
+    #[test]
+    fn pre_wear_save_round_trips_in_the_current_format() {
+        let slots = [Some((ItemId::StonePickaxe as u32, 1)), Some((ItemId::Pebble as u32, 20))];
+        let mut save_state = SaveState::default();
+        let (mut loaded, player) = decode_save(&save_bytes(4, &slots, &slots), &mut save_state).unwrap();
+        let mut inventory = Inventory::new();
+        inventory.slots[..player.inventory.len()].clone_from_slice(&player.inventory);
+
+        // the migrated chest comes from the chunk cache, written back in the current format
+        let bytes = save_state.build(&mut loaded, player.position, &inventory);
+        assert_eq!(bytes[4..8], VERSION.to_le_bytes());
+        let (loaded, player) = decode_save(&bytes, &mut SaveState::default()).unwrap();
+
+        let expected = vec![
+            Some(ItemStack::new(ItemId::StonePickaxe, 1)),
+            Some(ItemStack::new(ItemId::Pebble, 20)),
+        ];
+        assert_eq!(player.position, Vec2::new(12.0, 34.0));
+        assert_eq!(player.inventory[..expected.len()], expected[..]);
+        let chest = Chest::decode(loaded.get_tile_data(chest_pos()).unwrap()).unwrap();
+        assert_eq!(chest.slots[..expected.len()], expected[..]);
+    }
+
+    #[test]
+    fn corrupt_saves_never_replace_each_other() {
+        let dir = std::env::temp_dir().join(format!("astatine_corrupt_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("world.sav").to_string_lossy().to_string();
+
+        let first = corrupt_path(&path);
+        fs::write(&first, b"first").unwrap();
+        let second = corrupt_path(&path);
+        assert_ne!(first, second);
+        assert!(!Path::new(&second).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_blocks() {
+        // stone is fine, 99 is no block
+        for (block, known) in [(Block::Stone as u32, true), (99, false)] {
+            let mut chunk = Vec::new();
+            // blocks, walls, flips and state, each a single palette value, and no tile data
+            for value in [block, 0, 0, 0] {
+                write_u32(&mut chunk, 1);
+                write_u32(&mut chunk, value);
+            }
+            write_u32(&mut chunk, 0);
+
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(MAGIC);
+            write_u32(&mut bytes, VERSION);
+            write_f32(&mut bytes, 0.0);
+            write_f32(&mut bytes, 0.0);
+            write_slots(&mut bytes, &[]);
+            write_u32(&mut bytes, 1);
+            write_u32(&mut bytes, 0);
+            write_u32(&mut bytes, 0);
+            write_u32(&mut bytes, chunk.len() as u32);
+            bytes.extend(chunk);
+            assert_eq!(decode_save(&bytes, &mut SaveState::default()).is_ok(), known, "block {}", block);
+        }
+    }
+
+    #[test]
+    fn rejects_newer_saves() {
+        let mut bytes = save_bytes(VERSION, &[], &[]);
+        bytes[4..8].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(decode_save(&bytes, &mut SaveState::default()).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+
+use super::block::Block;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+#[allow(dead_code)]
+pub enum StateProperty {
+    Orientation,
+    GrowthStage,
+    Damage,
+    LiquidLevel,
+    Power,
+}
+
+impl StateProperty {
+    pub fn bits(&self) -> u32 {
+        match self {
+            StateProperty::Orientation => 2,
+            StateProperty::GrowthStage => 2,
+            StateProperty::Damage => 2,
+            StateProperty::LiquidLevel => 3,
+            StateProperty::Power => 4,
+        }
+    }
+
+    pub fn max(&self) -> u16 {
+        (1 << self.bits()) - 1
+    }
+}
+
+/// per tile state packed into 16 bits.
+/// the layout depends on the block, each property it declares in `Block::state_properties` gets its bits in order
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Default)]
+pub struct BlockState(pub u16);
+
+impl BlockState {
+    pub fn get(&self, block: Block, property: StateProperty) -> Option<u16> {
+        let shift = offset(block, property)?;
+        Some((self.0 >> shift) & property.max())
+    }
+
+    pub fn with(self, block: Block, property: StateProperty, value: u16) -> Self {
+        let Some(shift) = offset(block, property) else {
+            warn!("block {:?} has no state property {:?}", block, property);
+            return self;
+        };
+        let value = value.min(property.max());
+        Self((self.0 & !(property.max() << shift)) | (value << shift))
+    }
+}
+
+fn offset(block: Block, property: StateProperty) -> Option<u32> {
+    let mut shift = 0;
+    for block_property in block.state_properties() {
+        if *block_property == property {
+            return Some(shift);
+        }
+        shift += block_property.bits();
+    }
+    None
+}
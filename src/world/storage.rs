@@ -1,20 +1,30 @@
-use bevy::{math::ivec2, prelude::*, utils::HashMap};
+use std::io;
+
+use bevy::{math::ivec2, prelude::*, utils::{HashMap, HashSet}};
 use rand::prelude::*;
 
 use super::{
     block::Block,
     chunks::CHUNK_SIZE,
+    palette::PaletteStorage,
     position::{linearize, ChunkPos},
+    save::{write_u32, ByteReader},
+    state::BlockState,
 };
 
 pub const WORLD_CHUNK_SIZE: IVec2 = ivec2(32, 16);
+const CHUNK_AREA: usize = (CHUNK_SIZE * CHUNK_SIZE) as usize;
+
 pub const WORLD_BLOCK_SIZE: IVec2 = IVec2 {
     x: WORLD_CHUNK_SIZE.x * CHUNK_SIZE,
     y: WORLD_CHUNK_SIZE.y * CHUNK_SIZE,
 };
 
 #[derive(Resource)]
-pub struct WorldStorage(HashMap<ChunkPos, ChunkData>);
+pub struct WorldStorage {
+    chunks: HashMap<ChunkPos, ChunkData>,
+    dirty: HashSet<ChunkPos>,
+}
 
 impl WorldStorage {
     pub fn new() -> Self {
@@ -25,15 +35,30 @@ impl WorldStorage {
                 hashmap.insert(chunk_pos, ChunkData::new());
             }
         }
-        Self(hashmap)
+        Self {
+            dirty: hashmap.keys().copied().collect(),
+            chunks: hashmap,
+        }
     }
 
     pub fn get_chunk_data(&self, chunk_pos: ChunkPos) -> Option<&ChunkData> {
-        self.0.get(&chunk_pos)
+        self.chunks.get(&chunk_pos)
     }
 
     fn get_mut_chunk_data(&mut self, chunk_pos: ChunkPos) -> Option<&mut ChunkData> {
-        self.0.get_mut(&chunk_pos)
+        self.dirty.insert(chunk_pos);
+        self.chunks.get_mut(&chunk_pos)
+    }
+
+    /// replaces a chunk with data read from a save, without marking it dirty
+    pub fn insert_chunk_data(&mut self, chunk_pos: ChunkPos, chunk_data: ChunkData) {
+        self.dirty.remove(&chunk_pos);
+        self.chunks.insert(chunk_pos, chunk_data);
+    }
+
+    /// chunks modified since the last call
+    pub fn take_dirty(&mut self) -> Vec<ChunkPos> {
+        self.dirty.drain().collect()
     }
 
     pub fn get_block(&mut self, block_pos: IVec2) -> Option<Block> {
@@ -45,7 +70,7 @@ impl WorldStorage {
             );
             return None;
         };
-        let block_rel_pos = ivec2(block_pos.x % CHUNK_SIZE, block_pos.y % CHUNK_SIZE);
+        let block_rel_pos = ivec2(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
         chunk_data.get_block(block_rel_pos)
     }
 
@@ -58,11 +83,21 @@ impl WorldStorage {
             );
             return;
         };
-        let block_rel_pos = ivec2(block_pos.x % CHUNK_SIZE, block_pos.y % CHUNK_SIZE);
+        let block_rel_pos = ivec2(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
         chunk_data.set_block(block_rel_pos, block);
     }
 
-    #[allow(dead_code)]
+    /// drops unused palette entries in every chunk, call after bulk edits like world generation
+    pub fn compact(&mut self) {
+        for chunk_data in self.chunks.values_mut() {
+            chunk_data.compact();
+        }
+    }
+
+    pub fn heap_size(&self) -> usize {
+        self.chunks.values().map(|chunk_data| chunk_data.heap_size()).sum()
+    }
+
     pub fn get_wall(&mut self, block_pos: IVec2) -> Option<Block> {
         let chunk_pos = ChunkPos::from_block_pos(block_pos);
         let Some(chunk_data) = self.get_chunk_data(chunk_pos) else {
@@ -72,7 +107,7 @@ impl WorldStorage {
             );
             return None;
         };
-        let block_rel_pos = ivec2(block_pos.x % CHUNK_SIZE, block_pos.y % CHUNK_SIZE);
+        let block_rel_pos = ivec2(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
         chunk_data.get_wall(block_rel_pos)
     }
 
@@ -85,51 +120,191 @@ impl WorldStorage {
             );
             return;
         };
-        let block_rel_pos = ivec2(block_pos.x % CHUNK_SIZE, block_pos.y % CHUNK_SIZE);
+        let block_rel_pos = ivec2(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
         chunk_data.set_wall(block_rel_pos, block);
     }
+
+    pub fn get_state(&self, block_pos: IVec2) -> Option<BlockState> {
+        let chunk_pos = ChunkPos::from_block_pos(block_pos);
+        let Some(chunk_data) = self.get_chunk_data(chunk_pos) else {
+            warn!(
+                "could not get state at {} since there is no chunk data at {}",
+                block_pos, chunk_pos.0
+            );
+            return None;
+        };
+        let block_rel_pos = ivec2(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
+        chunk_data.get_state(block_rel_pos)
+    }
+
+    pub fn set_state(&mut self, block_pos: IVec2, state: BlockState) {
+        let chunk_pos = ChunkPos::from_block_pos(block_pos);
+        let Some(chunk_data) = self.get_mut_chunk_data(chunk_pos) else {
+            warn!(
+                "could not set state at {} since there is no chunk data at {}",
+                block_pos, chunk_pos.0
+            );
+            return;
+        };
+        let block_rel_pos = ivec2(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
+        chunk_data.set_state(block_rel_pos, state);
+    }
+
+    pub fn get_tile_data(&self, block_pos: IVec2) -> Option<&[u8]> {
+        let chunk_pos = ChunkPos::from_block_pos(block_pos);
+        let chunk_data = self.get_chunk_data(chunk_pos)?;
+        let block_rel_pos = ivec2(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
+        chunk_data.get_tile_data(block_rel_pos)
+    }
+
+    pub fn set_tile_data(&mut self, block_pos: IVec2, data: Option<Vec<u8>>) {
+        let chunk_pos = ChunkPos::from_block_pos(block_pos);
+        let Some(chunk_data) = self.get_mut_chunk_data(chunk_pos) else {
+            warn!(
+                "could not set tile data at {} since there is no chunk data at {}",
+                block_pos, chunk_pos.0
+            );
+            return;
+        };
+        let block_rel_pos = ivec2(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
+        chunk_data.set_tile_data(block_rel_pos, data);
+    }
 }
 
 pub struct ChunkData {
-    blocks: Vec<Block>,
-    walls: Vec<Block>,
-    flip: Vec<(bool, bool)>,
+    blocks: PaletteStorage<Block>,
+    walls: PaletteStorage<Block>,
+    flip: PaletteStorage<(bool, bool)>,
+    state: PaletteStorage<BlockState>,
+    /// larger per tile data that does not fit in `BlockState`, keyed by linearized position
+    tile_data: HashMap<usize, Vec<u8>>,
 }
 
 impl ChunkData {
     pub fn new() -> Self {
         let mut rng = rand::thread_rng();
+        let mut flip = PaletteStorage::new(CHUNK_AREA, (false, false));
+        for i in 0..CHUNK_AREA {
+            flip.set(i, (rng.gen_bool(0.5), rng.gen_bool(0.5)));
+        }
+
         Self {
-            blocks: vec![Block::Air; (CHUNK_SIZE * CHUNK_SIZE) as usize],
-            walls: vec![Block::Dirt; (CHUNK_SIZE * CHUNK_SIZE) as usize],
-            flip: (0..CHUNK_SIZE * CHUNK_SIZE)
-                .map(|_| (rng.gen_bool(0.5), rng.gen_bool(0.5)))
-                .collect(),
+            blocks: PaletteStorage::new(CHUNK_AREA, Block::Air),
+            walls: PaletteStorage::new(CHUNK_AREA, Block::Dirt),
+            flip,
+            state: PaletteStorage::new(CHUNK_AREA, BlockState::default()),
+            tile_data: HashMap::new(),
         }
     }
 
     pub fn get_block(&self, block_pos: IVec2) -> Option<Block> {
         let lin = linearize(block_pos);
-        Some(self.blocks[lin])
+        Some(self.blocks.get(lin))
     }
 
     pub fn get_wall(&self, block_pos: IVec2) -> Option<Block> {
         let lin = linearize(block_pos);
-        Some(self.walls[lin])
+        Some(self.walls.get(lin))
     }
 
+    /// replacing a block also clears its state and tile data
     pub fn set_block(&mut self, block_pos: IVec2, block: Block) {
         let lin = linearize(block_pos);
-        self.blocks[lin] = block;
+        if self.blocks.get(lin) == block {
+            return;
+        }
+        self.blocks.set(lin, block);
+        self.state.set(lin, BlockState::default());
+        self.tile_data.remove(&lin);
     }
 
     pub fn set_wall(&mut self, block_pos: IVec2, block: Block) {
         let lin = linearize(block_pos);
-        self.walls[lin] = block;
+        self.walls.set(lin, block);
+    }
+
+    pub fn get_state(&self, block_pos: IVec2) -> Option<BlockState> {
+        let lin = linearize(block_pos);
+        Some(self.state.get(lin))
+    }
+
+    pub fn set_state(&mut self, block_pos: IVec2, state: BlockState) {
+        let lin = linearize(block_pos);
+        self.state.set(lin, state);
+    }
+
+    pub fn get_tile_data(&self, block_pos: IVec2) -> Option<&[u8]> {
+        let lin = linearize(block_pos);
+        self.tile_data.get(&lin).map(|data| data.as_slice())
+    }
+
+    pub fn set_tile_data(&mut self, block_pos: IVec2, data: Option<Vec<u8>>) {
+        let lin = linearize(block_pos);
+        match data {
+            Some(data) => self.tile_data.insert(lin, data),
+            None => self.tile_data.remove(&lin),
+        };
     }
 
     pub fn get_flip(&self, block_pos: IVec2) -> Option<(bool, bool)> {
         let lin = linearize(block_pos);
-        Some(self.flip[lin])
+        Some(self.flip.get(lin))
+    }
+
+    pub fn compact(&mut self) {
+        self.blocks.compact();
+        self.walls.compact();
+        self.state.compact();
+    }
+
+    pub fn heap_size(&self) -> usize {
+        self.blocks.heap_size()
+            + self.walls.heap_size()
+            + self.flip.heap_size()
+            + self.state.heap_size()
+            + self.tile_data.values().map(|data| data.capacity()).sum::<usize>()
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        self.blocks.encode(out, |block| block as u32);
+        self.walls.encode(out, |block| block as u32);
+        self.flip.encode(out, |(x, y)| x as u32 | (y as u32) << 1);
+        self.state.encode(out, |state| state.0 as u32);
+        write_u32(out, self.tile_data.len() as u32);
+        for (lin, data) in self.tile_data.iter() {
+            write_u32(out, *lin as u32);
+            write_u32(out, data.len() as u32);
+            out.extend_from_slice(data);
+        }
+    }
+
+    /// chunks from saves without `has_state` have neither block state nor tile data
+    pub fn decode(reader: &mut ByteReader, has_state: bool) -> io::Result<Self> {
+        let blocks = PaletteStorage::decode(CHUNK_AREA, reader, Block::from)?;
+        let walls = PaletteStorage::decode(CHUNK_AREA, reader, Block::from)?;
+        let flip = PaletteStorage::decode(CHUNK_AREA, reader, |raw| Some((raw & 1 != 0, raw & 2 != 0)))?;
+        if !has_state {
+            return Ok(Self {
+                blocks,
+                walls,
+                flip,
+                state: PaletteStorage::new(CHUNK_AREA, BlockState::default()),
+                tile_data: HashMap::new(),
+            });
+        }
+        let state = PaletteStorage::decode(CHUNK_AREA, reader, |raw| Some(BlockState(raw as u16)))?;
+
+        let mut tile_data = HashMap::new();
+        let tile_data_count = reader.read_u32()?;
+        for _ in 0..tile_data_count {
+            let lin = reader.read_u32()? as usize;
+            let len = reader.read_u32()? as usize;
+            if lin >= CHUNK_AREA {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "tile data out of bounds"));
+            }
+            tile_data.insert(lin, reader.take(len)?.to_vec());
+        }
+
+        Ok(Self { blocks, walls, flip, state, tile_data })
     }
 }
@@ -0,0 +1,337 @@
+use std::io;
+
+use bevy::{math::{ivec2, vec2}, prelude::*, utils::{HashMap, HashSet}};
+use bevy_egui::{egui, EguiContexts};
+
+use crate::{
+    controls::Action,
+    entities::item::SpawnItem,
+    items::ItemStack,
+    physics::{contacts::{CollisionLayers, Trigger, TriggerEvent}, Collider},
+    player::{inventory::{add_to_slots, slot_text, Inventory, HOTBAR_SLOTS}, player::Player, target::{BlockTarget, Reach}},
+};
+
+use super::{
+    block::Block,
+    chunks::{LoadedChunks, RedrawTile, ReloadChunk, CHUNK_SIZE},
+    position::ChunkPos,
+    save::{read_slots, write_slots, ByteReader},
+    state::StateProperty,
+    storage::WorldStorage,
+};
+
+pub const CHEST_SLOTS: usize = 16;
+const CHEST_COLUMNS: usize = 4;
+
+/// block position of the tile a tile entity belongs to
+#[derive(Component, Clone, Copy)]
+pub struct TileEntity(pub IVec2);
+
+#[derive(Component)]
+pub struct Chest {
+    pub slots: Vec<Option<ItemStack>>,
+}
+
+/// powered while something stands on it, counts the walkers inside its trigger
+#[derive(Component, Default)]
+pub struct PressurePlate {
+    pub occupants: u32,
+}
+
+/// spawned tile entities by block position, and the chunks they were spawned for
+#[derive(Resource, Default)]
+pub struct TileEntities {
+    entities: HashMap<IVec2, Entity>,
+    chunks: HashSet<ChunkPos>,
+}
+
+impl TileEntities {
+    pub fn get(&self, block_pos: IVec2) -> Option<Entity> {
+        self.entities.get(&block_pos).copied()
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct OpenChest(pub Option<Entity>);
+
+impl Chest {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![None; CHEST_SLOTS],
+        }
+    }
+
+    /// same as `Inventory::add`, returns how many items did not fit
+    pub fn add(&mut self, stack: ItemStack) -> u32 {
+        add_to_slots(&mut self.slots, stack)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_slots(&mut out, &self.slots);
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut slots = read_slots(&mut ByteReader::new(bytes))?;
+        slots.resize(CHEST_SLOTS, None);
+        Ok(Self { slots })
+    }
+}
+
+/// keeps tile entities in sync with `LoadedChunks`:
+/// spawns them for newly loaded chunks, despawns them for unloaded ones
+/// and rescans chunks that were edited to pick up placed and destroyed blocks
+pub fn sync_tile_entities(
+    mut commands: Commands,
+    loaded_chunks: Res<LoadedChunks>,
+    world_storage: Res<WorldStorage>,
+    mut tile_entities: ResMut<TileEntities>,
+    mut reload_event: EventReader<ReloadChunk>,
+    chest_query: Query<&Chest>,
+    mut item_event: EventWriter<SpawnItem>,
+) {
+    // contents are already persisted by `persist_tile_entities`, so unloading only despawns
+    let unloaded: Vec<ChunkPos> = tile_entities.chunks.iter()
+        .filter(|chunk_pos| loaded_chunks.get_chunk(**chunk_pos).is_none())
+        .copied()
+        .collect();
+    for chunk_pos in unloaded {
+        tile_entities.chunks.remove(&chunk_pos);
+        tile_entities.entities.retain(|block_pos, entity| {
+            if ChunkPos::from_block_pos(*block_pos) != chunk_pos { return true; };
+            commands.entity(*entity).despawn_recursive();
+            false
+        });
+    }
+
+    let mut to_scan: Vec<ChunkPos> = loaded_chunks.chunk_positions()
+        .filter(|chunk_pos| !tile_entities.chunks.contains(chunk_pos))
+        .copied()
+        .collect();
+    to_scan.extend(reload_event.iter()
+        .map(|ev| ev.0)
+        .filter(|chunk_pos| tile_entities.chunks.contains(chunk_pos)));
+
+    for chunk_pos in to_scan {
+        tile_entities.chunks.insert(chunk_pos);
+        let Some(chunk_data) = world_storage.get_chunk_data(chunk_pos) else { continue; };
+
+        // destroyed with their block, dropping whatever they held
+        tile_entities.entities.retain(|block_pos, entity| {
+            if ChunkPos::from_block_pos(*block_pos) != chunk_pos { return true; };
+            let rel_pos = ivec2(block_pos.x.rem_euclid(CHUNK_SIZE), block_pos.y.rem_euclid(CHUNK_SIZE));
+            if chunk_data.get_block(rel_pos).unwrap().has_tile_entity() { return true; };
+
+            if let Ok(chest) = chest_query.get(*entity) {
+                for stack in chest.slots.iter().flatten() {
+                    item_event.send(SpawnItem {
+                        position: block_pos.as_vec2() * 8.0,
+                        stack: *stack,
+                    });
+                }
+            }
+            commands.entity(*entity).despawn_recursive();
+            false
+        });
+
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let block = chunk_data.get_block(ivec2(x, y)).unwrap();
+                if !block.has_tile_entity() { continue; };
+                let block_pos = chunk_pos.0 * CHUNK_SIZE + ivec2(x, y);
+                if tile_entities.entities.contains_key(&block_pos) { continue; };
+
+                let entity = match block {
+                    Block::PressurePlate => commands.spawn((
+                        TileEntity(block_pos),
+                        PressurePlate::default(),
+                        TransformBundle::from_transform(Transform::from_translation((block_pos.as_vec2() * 8.0).extend(0.0))),
+                        // a thin strip along the bottom of the tile, where feet end up
+                        Collider { size: vec2(8.0, 2.0), offset: vec2(0.0, -3.0) },
+                        CollisionLayers::new(CollisionLayers::TRIGGER, CollisionLayers::PLAYER | CollisionLayers::MOB),
+                        Trigger,
+                    )).id(),
+                    _ => {
+                        let chest = match chunk_data.get_tile_data(ivec2(x, y)) {
+                            Some(data) => Chest::decode(data).unwrap_or_else(|err| {
+                                warn!("could not read chest at {}: {}", block_pos, err);
+                                Chest::new()
+                            }),
+                            None => Chest::new(),
+                        };
+                        commands.spawn((TileEntity(block_pos), chest)).id()
+                    }
+                };
+                tile_entities.entities.insert(block_pos, entity);
+            }
+        }
+    }
+}
+
+/// writes changed tile entity data back into the world so saves always include it.
+/// freshly spawned chests were just read from the world, so they are not written back
+pub fn persist_tile_entities(
+    chest_query: Query<(&TileEntity, Ref<Chest>), Changed<Chest>>,
+    mut world_storage: ResMut<WorldStorage>,
+) {
+    for (tile_entity, chest) in chest_query.iter() {
+        if chest.is_added() { continue; };
+        if world_storage.get_block(tile_entity.0) != Some(Block::Chest) { continue; };
+        world_storage.set_tile_data(tile_entity.0, Some(chest.encode()));
+    }
+}
+
+/// counts walkers entering and leaving plates and stores whether they are pressed in the block state
+pub fn update_pressure_plates(
+    mut trigger_event: EventReader<TriggerEvent>,
+    mut plate_query: Query<(&TileEntity, &mut PressurePlate)>,
+    mut world_storage: ResMut<WorldStorage>,
+    mut redraw_event: EventWriter<RedrawTile>,
+) {
+    for ev in trigger_event.iter() {
+        match *ev {
+            TriggerEvent::Enter { trigger, .. } => {
+                let Ok((_, mut plate)) = plate_query.get_mut(trigger) else { continue; };
+                plate.occupants += 1;
+            }
+            TriggerEvent::Exit { trigger, .. } => {
+                let Ok((_, mut plate)) = plate_query.get_mut(trigger) else { continue; };
+                plate.occupants = plate.occupants.saturating_sub(1);
+            }
+        }
+    }
+
+    // also runs for freshly spawned plates, releasing ones saved while pressed
+    for (tile_entity, plate) in plate_query.iter_mut() {
+        if !plate.is_changed() { continue; };
+        if world_storage.get_block(tile_entity.0) != Some(Block::PressurePlate) { continue; };
+        let Some(state) = world_storage.get_state(tile_entity.0) else { continue; };
+        let power = if plate.occupants > 0 { StateProperty::Power.max() } else { 0 };
+        if state.get(Block::PressurePlate, StateProperty::Power) == Some(power) { continue; };
+
+        world_storage.set_state(tile_entity.0, state.with(Block::PressurePlate, StateProperty::Power, power));
+        // power only changes the look of a plate, not its shape
+        redraw_event.send(RedrawTile(tile_entity.0));
+    }
+}
+
+pub fn open_chest(
+    mut contexts: EguiContexts,
+    block_target: Res<BlockTarget>,
+    actions: Res<Input<Action>>,
+    tile_entities: Res<TileEntities>,
+    chest_query: Query<(), With<Chest>>,
+    mut open_chest: ResMut<OpenChest>,
+) {
+    if !actions.just_pressed(Action::Interact) { return; };
+    if contexts.ctx_mut().wants_pointer_input() { return; };
+    let Some(target) = block_target.solid() else { return; };
+    let Some(entity) = tile_entities.get(target) else { return; };
+    if chest_query.contains(entity) {
+        open_chest.0 = Some(entity);
+    }
+}
+
+/// clicking a chest slot moves its stack into the player's inventory,
+/// clicking an inventory slot moves it into the chest.
+/// closes once the player walks out of reach
+pub fn chest_window(
+    mut contexts: EguiContexts,
+    reach: Res<Reach>,
+    mut open_chest: ResMut<OpenChest>,
+    mut chest_query: Query<(&TileEntity, &mut Chest)>,
+    mut player_query: Query<(&Transform, &mut Inventory), With<Player>>,
+) {
+    let Some(entity) = open_chest.0 else { return; };
+    let Ok((tile_entity, mut chest)) = chest_query.get_mut(entity) else {
+        open_chest.0 = None;
+        return;
+    };
+    let Ok((player_transform, mut inventory)) = player_query.get_single_mut() else { return; };
+    let player_pos = player_transform.translation.truncate() / 8.0;
+    if tile_entity.0.as_vec2().distance(player_pos) > reach.0 {
+        open_chest.0 = None;
+        return;
+    }
+
+    let mut open = true;
+    let mut clicked = None;
+    let mut clicked_inventory = None;
+    egui::Window::new("chest").open(&mut open).show(contexts.ctx_mut(), |ui| {
+        egui::Grid::new("chest_slots").show(ui, |ui| {
+            for (i, slot) in chest.slots.iter().enumerate() {
+                if ui.add_sized([64.0, 32.0], egui::Button::new(slot_text(slot))).clicked() {
+                    clicked = Some(i);
+                }
+                if (i + 1) % CHEST_COLUMNS == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+        ui.separator();
+        egui::Grid::new("chest_inventory_slots").show(ui, |ui| {
+            for (i, slot) in inventory.slots.iter().enumerate() {
+                if ui.add_sized([64.0, 32.0], egui::Button::new(slot_text(slot))).clicked() {
+                    clicked_inventory = Some(i);
+                }
+                if (i + 1) % HOTBAR_SLOTS == 0 {
+                    ui.end_row();
+                }
+            }
+        });
+    });
+
+    if let Some(i) = clicked {
+        if let Some(stack) = chest.slots[i] {
+            chest.slots[i] = match inventory.add(stack) {
+                0 => None,
+                count => Some(ItemStack { count, ..stack }),
+            };
+        }
+    }
+    if let Some(i) = clicked_inventory {
+        if let Some(stack) = inventory.slots[i] {
+            inventory.slots[i] = match chest.add(stack) {
+                0 => None,
+                count => Some(ItemStack { count, ..stack }),
+            };
+        }
+    }
+
+    if !open {
+        open_chest.0 = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::items::ItemId;
+
+    use super::*;
+
+    #[test]
+    fn only_changed_chests_are_persisted() {
+        let block_pos = ivec2(40, 50);
+        let mut world_storage = WorldStorage::new();
+        world_storage.set_block(block_pos, Block::Chest);
+        world_storage.take_dirty();
+
+        let mut app = App::new();
+        app.insert_resource(world_storage);
+        app.add_systems(Update, persist_tile_entities);
+        let entity = app.world.spawn((TileEntity(block_pos), Chest::new())).id();
+
+        // spawning a chest for a loaded chunk leaves the chunk as it was
+        app.update();
+        assert!(app.world.resource_mut::<WorldStorage>().take_dirty().is_empty());
+
+        app.world.get_mut::<Chest>(entity).unwrap().add(ItemStack::new(ItemId::Stone, 3));
+        app.update();
+        let mut world_storage = app.world.resource_mut::<WorldStorage>();
+        let dirty = world_storage.take_dirty();
+        assert!(dirty.len() == 1 && dirty[0] == ChunkPos::from_block_pos(block_pos));
+        let chest = Chest::decode(world_storage.get_tile_data(block_pos).unwrap()).unwrap();
+        assert_eq!(chest.slots[0], Some(ItemStack::new(ItemId::Stone, 3)));
+    }
+}